@@ -3,6 +3,7 @@
 #include <lualib.h>  /* Prototype for luaL_openlibs(), */
 #include <stdlib.h>
 #include <stdio.h>
+#include <string.h>
 
 #include <time.h>
 #include <sys/time.h>
@@ -12,31 +13,40 @@
 #include <mach/mach.h>
 #endif
 
-lua_State *L = 0;
+// Every function takes the interpreter it works on, so any number of them can be alive at once
+// (see lua.rs).  Nothing here is global: the orders and trade ids live in each state's registry.
 
-enum DECISION {
-    NOOP = 0,
-    LONG = 1,
-    SHORT = 2,
-    CLOSE = 3
-} trading_decisions;
+// keep in sync with OrderAction in order.rs
+enum ORDER_ACTION {
+    BUY = 0,
+    SELL = 1,
+    CLOSE = 2,
+    CLOSE_ALL = 3,
+    MODIFY = 4
+} order_actions;
 
-int trading_decision;
+// One order the strategy placed during a callback.  The has_* fields are 0 when the strategy
+// left the matching value out.  `comment` belongs to the interpreter and is only valid until
+// lua_bridge_clear_orders() is called.
+struct order {
+    int action;
+    int id;
+    float size;
+    int has_stop_loss;
+    float stop_loss;
+    int has_take_profit;
+    float take_profit;
+    int magic;
+    const char *comment;
+};
 
-void ensure_initialized() {
+void ensure_initialized(lua_State *L) {
     if(!L) {
         fprintf(stderr, "\nLua must be initialized before calling this function\n");
         exit(1);
     }
 }
 
-void ensure_not_initialized() {
-    if(L) {
-        fprintf(stderr, "\nAttempted to initialize an existing Lua session\n");
-        exit(1);
-    }
-}
-
 int get_nanoseconds(lua_State *L) {
     struct timespec ts;
 
@@ -55,34 +65,1112 @@ int get_nanoseconds(lua_State *L) {
     return ts.tv_nsec;
 }
 
-int open_long_trade(lua_State *L) {
-    trading_decision = LONG;
+// ===== RANDOM FUNCTIONS ==========================================================================
+
+// math.random() and math.randomseed() are replaced by a generator kept in each state's registry.
+// The C library's rand() they'd use is shared by every state and differs between platforms, so
+// a seed couldn't replay a run with it.
+
+// xorshift64*
+unsigned long long next_random(lua_State *L) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "random_state");
+    unsigned long long *state = (unsigned long long *)lua_touserdata(L, -1);
+    lua_pop(L, 1); // the registry keeps the userdata alive
+
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+
+    return *state * 2685821657736338717ULL;
+}
+
+// The seed is mixed (splitmix64) so nearby seeds, e.g. consecutive simulation ids, start far
+// apart.  xorshift never leaves a state of 0, so that's avoided.
+void set_random_seed(lua_State *L, unsigned long long seed) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "random_state");
+    unsigned long long *state = (unsigned long long *)lua_touserdata(L, -1);
+    lua_pop(L, 1);
+
+    seed += 0x9E3779B97F4A7C15ULL;
+    seed = (seed ^ (seed >> 30)) * 0xBF58476D1CE4E5B9ULL;
+    seed = (seed ^ (seed >> 27)) * 0x94D049BB133111EBULL;
+    seed ^= seed >> 31;
+
+    *state = seed ? seed : 1;
+}
+
+// math.random([m [, n]]), the same as Lua's own: a float in [0, 1), an integer in [1, m] or one
+// in [m, n]
+int random_number(lua_State *L) {
+    // the top 53 bits, as many as a double holds
+    lua_Number r = (lua_Number)(next_random(L) >> 11) * (1.0 / 9007199254740992.0);
+    lua_Number low, up;
+
+    switch(lua_gettop(L)) {
+        case 0:
+            lua_pushnumber(L, r);
+            return 1;
+        case 1:
+            low = 1;
+            up = luaL_checknumber(L, 1);
+            break;
+        case 2:
+            low = luaL_checknumber(L, 1);
+            up = luaL_checknumber(L, 2);
+            break;
+        default:
+            return luaL_error(L, "wrong number of arguments");
+    }
+
+    luaL_argcheck(L, low <= up, lua_gettop(L), "interval is empty");
+
+    // r * (up - low + 1) isn't negative, so the cast rounds down
+    lua_pushnumber(L, (lua_Number)(long long)(r * (up - low + 1)) + low);
+    return 1;
+}
+
+int random_seed(lua_State *L) {
+    set_random_seed(L, (unsigned long long)(long long)luaL_checknumber(L, 1));
     return 0;
 }
 
-int open_short_trade(lua_State *L) {
-    trading_decision = SHORT;
+// Seeds math.random() before the script is loaded.  Every `stream`, i.e. simulation, gets its own
+// sequence from the one seed.
+void lua_bridge_seed_random(lua_State *L, unsigned int seed, unsigned int stream) {
+    ensure_initialized(L);
+    set_random_seed(L, ((unsigned long long)seed << 32) | stream);
+}
+
+// ===== ORDER FUNCTIONS ===========================================================================
+
+// Orders are queued in the registry as tables and handed to Rust once the callback placing them
+// returns, so every call made during it is carried out, in the order it was made.
+
+void queue_order(lua_State *L, int order) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "orders");
+    lua_pushvalue(L, order);
+    lua_rawseti(L, -2, lua_rawlen(L, -2) + 1);
+    lua_pop(L, 1);
+}
+
+// Trade ids are handed out as the orders are placed so buy{} and sell{} can return them.  They
+// start at 1 in every interpreter, i.e. in every simulation.
+int next_trade_id(lua_State *L) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "next_trade_id");
+    int id = (int)lua_tointeger(L, -1);
+    lua_pop(L, 1);
+
+    lua_pushinteger(L, id + 1);
+    lua_setfield(L, LUA_REGISTRYINDEX, "next_trade_id");
+
+    return id;
+}
+
+// Raises an error for any key of the options table at `index` that isn't in `allowed`, which
+// ends with NULL.  Catches typos like stoploss= that would otherwise be ignored.
+void check_option_names(lua_State *L, int index, const char **allowed) {
+    lua_pushnil(L);
+
+    while(lua_next(L, index)) {
+        lua_pop(L, 1); // the value
+
+        const char *name = lua_type(L, -1) == LUA_TSTRING ? lua_tostring(L, -1) : NULL;
+        const char **option;
+        int known = 0;
+
+        for(option = allowed; name && *option; option++) {
+            if(!strcmp(name, *option))
+                known = 1;
+        }
+
+        if(!known)
+            luaL_error(L, "unknown order option '%s'", name ? name : luaL_typename(L, -1));
+    }
+}
+
+// Copies options[name] to order[name] when it's set, raising an error when it isn't a `type`.
+// Prices and sizes can't be negative.
+void copy_option(lua_State *L, int options, int order, const char *name, int type) {
+    lua_getfield(L, options, name);
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+        return;
+    }
+
+    if(lua_type(L, -1) != type)
+        luaL_error(L, "%s must be a %s", name, lua_typename(L, type));
+
+    if(type == LUA_TNUMBER && lua_tonumber(L, -1) < 0)
+        luaL_error(L, "%s can't be negative", name);
+
+    lua_setfield(L, order, name);
+}
+
+// buy{size=, stop_loss=, take_profit=, comment=, magic=} and sell{...}, all optional.  Returns
+// the id of the trade the order will open.
+int open_order(lua_State *L, int action) {
+    static const char *allowed[] = {
+        "size", "stop_loss", "take_profit", "comment", "magic", NULL
+    };
+
+    int has_options = !lua_isnoneornil(L, 1);
+
+    if(has_options) {
+        luaL_checktype(L, 1, LUA_TTABLE);
+        check_option_names(L, 1, allowed);
+    }
+
+    lua_newtable(L);
+    int order = lua_gettop(L);
+
+    lua_pushinteger(L, action);
+    lua_setfield(L, order, "action");
+
+    if(has_options) {
+        copy_option(L, 1, order, "size", LUA_TNUMBER);
+        copy_option(L, 1, order, "stop_loss", LUA_TNUMBER);
+        copy_option(L, 1, order, "take_profit", LUA_TNUMBER);
+        copy_option(L, 1, order, "comment", LUA_TSTRING);
+        copy_option(L, 1, order, "magic", LUA_TNUMBER);
+    }
+
+    lua_getfield(L, order, "size");
+    if(!lua_isnil(L, -1) && lua_tonumber(L, -1) <= 0)
+        luaL_error(L, "size must be > 0");
+    lua_pop(L, 1);
+
+    int id = next_trade_id(L);
+
+    lua_pushinteger(L, id);
+    lua_setfield(L, order, "id");
+
+    queue_order(L, order);
+
+    lua_pushinteger(L, id);
+    return 1;
+}
+
+int order_buy(lua_State *L) {
+    return open_order(L, BUY);
+}
+
+int order_sell(lua_State *L) {
+    return open_order(L, SELL);
+}
+
+// close(id)
+int order_close(lua_State *L) {
+    int id = (int)luaL_checkinteger(L, 1);
+
+    lua_newtable(L);
+
+    lua_pushinteger(L, CLOSE);
+    lua_setfield(L, -2, "action");
+
+    lua_pushinteger(L, id);
+    lua_setfield(L, -2, "id");
+
+    queue_order(L, lua_gettop(L));
     return 0;
 }
 
+int order_close_all(lua_State *L) {
+    lua_newtable(L);
+
+    lua_pushinteger(L, CLOSE_ALL);
+    lua_setfield(L, -2, "action");
+
+    queue_order(L, lua_gettop(L));
+    return 0;
+}
+
+// modify(id, {stop_loss=, take_profit=}).  Leaving a value out keeps it, 0 removes it.
+int order_modify(lua_State *L) {
+    static const char *allowed[] = { "stop_loss", "take_profit", NULL };
+
+    int id = (int)luaL_checkinteger(L, 1);
+    luaL_checktype(L, 2, LUA_TTABLE);
+    check_option_names(L, 2, allowed);
+
+    lua_newtable(L);
+    int order = lua_gettop(L);
+
+    lua_pushinteger(L, MODIFY);
+    lua_setfield(L, order, "action");
+
+    lua_pushinteger(L, id);
+    lua_setfield(L, order, "id");
+
+    copy_option(L, 2, order, "stop_loss", LUA_TNUMBER);
+    copy_option(L, 2, order, "take_profit", LUA_TNUMBER);
+
+    queue_order(L, order);
+    return 0;
+}
+
+// the original trading calls, kept for older strategies
+
+int open_long_trade(lua_State *L) {
+    lua_settop(L, 0);
+    return open_order(L, BUY);
+}
+
+int open_short_trade(lua_State *L) {
+    lua_settop(L, 0);
+    return open_order(L, SELL);
+}
+
 int close_trade(lua_State *L) {
-    trading_decision = CLOSE;
+    return order_close_all(L);
+}
+
+// called once Rust has copied the orders
+void lua_bridge_clear_orders(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "orders");
+}
+
+int lua_bridge_order_count(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "orders");
+    int count = (int)lua_rawlen(L, -1);
+    lua_pop(L, 1);
+
+    return count;
+}
+
+// `index` starts at 0
+void lua_bridge_get_order(lua_State *L, int index, struct order *out) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "orders");
+    lua_rawgeti(L, -1, index + 1);
+
+    lua_getfield(L, -1, "action");
+    out->action = (int)lua_tointeger(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "id");
+    out->id = (int)lua_tointeger(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "size");
+    out->size = lua_isnil(L, -1) ? 1.0f : (float)lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "stop_loss");
+    out->has_stop_loss = !lua_isnil(L, -1);
+    out->stop_loss = (float)lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "take_profit");
+    out->has_take_profit = !lua_isnil(L, -1);
+    out->take_profit = (float)lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "magic");
+    out->magic = (int)lua_tointeger(L, -1);
+    lua_pop(L, 1);
+
+    // the order table in the registry keeps the string alive
+    lua_getfield(L, -1, "comment");
+    out->comment = lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    lua_pop(L, 2);
+}
+
+// ===== TICK FUNCTIONS ============================================================================
+
+// The tick and ctx tables passed to on_tick(tick, ctx), and after their own arguments to the
+// other callbacks.  They're made once and refilled for every tick rather than built anew, so a
+// strategy shouldn't hold on to them.
+
+struct tick_argument {
+    double time; // seconds since the epoch
+    double bid;
+    double ask;
+    int hour;
+    int minute;
+    int weekday; // 0 for Sunday through 6 for Saturday
+    int day; // of the month
+    int asian_open;
+    int sydney_open;
+    int tokyo_open;
+    int london_open;
+    int new_york_open;
+};
+
+struct context {
+    double balance;
+    double equity;
+    double drawdown; // a percentage, <= 0
+    double free_margin;
+    int open_trades;
+
+    // over the last TICK_WINDOW_SECONDS, for staying out of spread spikes and dead markets
+    double tick_velocity;
+    double spread_average;
+    double spread_max;
+    double realized_volatility;
+    double seconds_since_last_tick;
+};
+
+void set_number_field(lua_State *L, const char *name, double value) {
+    lua_pushnumber(L, value);
+    lua_setfield(L, -2, name);
+}
+
+void set_boolean_field(lua_State *L, const char *name, int value) {
+    lua_pushboolean(L, value);
+    lua_setfield(L, -2, name);
+}
+
+// The globals strategies read before tick and ctx were passed to the callbacks, kept so they
+// still run.  They're filled from the same structs.
+void set_tick_globals(lua_State *L, struct tick_argument *tick, struct context *context) {
+    time_t seconds = (time_t)tick->time;
+    struct tm utc;
+    char iso[32];
+
+    gmtime_r(&seconds, &utc);
+    strftime(iso, sizeof(iso), "%Y-%m-%d %H:%M:%S", &utc);
+
+    lua_pushglobaltable(L);
+
+    set_number_field(L, "current_bid", tick->bid);
+    set_number_field(L, "current_ask", tick->ask);
+    set_number_field(L, "current_spread", tick->ask - tick->bid);
+    set_boolean_field(L, "has_open_trades", context->open_trades > 0);
+
+    set_number_field(L, "current_time", tick->time);
+    set_number_field(L, "current_hour", tick->hour);
+    set_number_field(L, "current_minute", tick->minute);
+    set_number_field(L, "current_weekday", tick->weekday);
+    set_number_field(L, "current_day", tick->day);
+
+    lua_pushstring(L, iso);
+    lua_setfield(L, -2, "current_time_iso");
+
+    set_boolean_field(L, "asian_open", tick->asian_open);
+    set_boolean_field(L, "sydney_open", tick->sydney_open);
+    set_boolean_field(L, "tokyo_open", tick->tokyo_open);
+    set_boolean_field(L, "london_open", tick->london_open);
+    set_boolean_field(L, "new_york_open", tick->new_york_open);
+
+    set_number_field(L, "tick_velocity", context->tick_velocity);
+    set_number_field(L, "spread_average", context->spread_average);
+    set_number_field(L, "spread_max", context->spread_max);
+    set_number_field(L, "realized_volatility", context->realized_volatility);
+    set_number_field(L, "seconds_since_last_tick", context->seconds_since_last_tick);
+
+    lua_pop(L, 1);
+}
+
+// Refills tick, ctx, the account table and the older globals in one go.
+void lua_bridge_set_tick(lua_State *L, struct tick_argument *tick, struct context *context) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "tick");
+
+    set_number_field(L, "time", tick->time);
+    set_number_field(L, "bid", tick->bid);
+    set_number_field(L, "ask", tick->ask);
+    set_number_field(L, "spread", tick->ask - tick->bid);
+    set_number_field(L, "hour", tick->hour);
+    set_number_field(L, "minute", tick->minute);
+    set_number_field(L, "weekday", tick->weekday);
+    set_number_field(L, "day", tick->day);
+    set_boolean_field(L, "asian_open", tick->asian_open);
+    set_boolean_field(L, "sydney_open", tick->sydney_open);
+    set_boolean_field(L, "tokyo_open", tick->tokyo_open);
+    set_boolean_field(L, "london_open", tick->london_open);
+    set_boolean_field(L, "new_york_open", tick->new_york_open);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "context");
+
+    set_number_field(L, "balance", context->balance);
+    set_number_field(L, "equity", context->equity);
+    set_number_field(L, "drawdown", context->drawdown);
+    set_number_field(L, "free_margin", context->free_margin);
+    set_number_field(L, "open_trades", context->open_trades);
+    set_number_field(L, "tick_velocity", context->tick_velocity);
+    set_number_field(L, "spread_average", context->spread_average);
+    set_number_field(L, "spread_max", context->spread_max);
+    set_number_field(L, "realized_volatility", context->realized_volatility);
+    set_number_field(L, "seconds_since_last_tick", context->seconds_since_last_tick);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "account");
+
+    set_number_field(L, "balance", context->balance);
+    set_number_field(L, "equity", context->equity);
+    set_number_field(L, "drawdown", context->drawdown);
+    set_number_field(L, "free_margin", context->free_margin);
+
+    lua_pop(L, 3);
+
+    set_tick_globals(L, tick, context);
+}
+
+// pushes tick and ctx for lua_bridge_call()
+void lua_bridge_push_tick_arguments(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "tick");
+    lua_getfield(L, LUA_REGISTRYINDEX, "context");
+}
+
+// ===== ACCOUNT FUNCTIONS =========================================================================
+
+// A trade that's still open, published before every callback.  The has_* fields are 0 when the
+// trade has no stop loss or take profit.
+struct position {
+    int id;
+    int is_long;
+    float size;
+    float open_price;
+    double open_time; // seconds since the epoch
+    float profit;
+    int has_stop_loss;
+    float stop_loss;
+    int has_take_profit;
+    float take_profit;
+    int magic;
+    const char *comment;
+};
+
+// __newindex of the account table: it only reflects the simulation
+int read_only(lua_State *L) {
+    return luaL_error(L, "the account table is read-only");
+}
+
+// Returns a copy of the open positions, so changing it doesn't change anything that's published.
+int positions(lua_State *L) {
+    int i;
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "positions");
+    int source = lua_gettop(L);
+    int count = (int)lua_rawlen(L, source);
+
+    lua_createtable(L, count, 0);
+
+    for(i = 1; i <= count; i++) {
+        lua_rawgeti(L, source, i);
+        lua_newtable(L);
+
+        // stack: source, list, position, copy
+        lua_pushnil(L);
+        while(lua_next(L, -3)) {
+            lua_pushvalue(L, -2);
+            lua_insert(L, -2);
+            lua_rawset(L, -4);
+        }
+
+        lua_rawseti(L, -3, i);
+        lua_pop(L, 1);
+    }
+
+    return 1;
+}
+
+void lua_bridge_clear_positions(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "positions");
+}
+
+void lua_bridge_push_position(lua_State *L, struct position *position) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "positions");
+    lua_newtable(L);
+
+    lua_pushinteger(L, position->id);
+    lua_setfield(L, -2, "id");
+
+    lua_pushstring(L, position->is_long ? "long" : "short");
+    lua_setfield(L, -2, "direction");
+
+    lua_pushnumber(L, position->size);
+    lua_setfield(L, -2, "size");
+
+    lua_pushnumber(L, position->open_price);
+    lua_setfield(L, -2, "open_price");
+
+    lua_pushnumber(L, position->open_time);
+    lua_setfield(L, -2, "open_time");
+
+    lua_pushnumber(L, position->profit);
+    lua_setfield(L, -2, "profit");
+
+    if(position->has_stop_loss) {
+        lua_pushnumber(L, position->stop_loss);
+        lua_setfield(L, -2, "stop_loss");
+    }
+
+    if(position->has_take_profit) {
+        lua_pushnumber(L, position->take_profit);
+        lua_setfield(L, -2, "take_profit");
+    }
+
+    lua_pushinteger(L, position->magic);
+    lua_setfield(L, -2, "magic");
+
+    lua_pushstring(L, position->comment);
+    lua_setfield(L, -2, "comment");
+
+    lua_rawseti(L, -2, lua_rawlen(L, -2) + 1);
+    lua_pop(L, 1);
+}
+
+// Refreshes the profits of the positions pushed since they were last cleared, in the same order.
+void lua_bridge_set_position_profits(lua_State *L, float *profits, int count) {
+    int i;
+
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "positions");
+
+    for(i = 0; i < count; i++) {
+        lua_rawgeti(L, -1, i + 1);
+        set_number_field(L, "profit", profits[i]);
+        lua_pop(L, 1);
+    }
+
+    lua_pop(L, 1);
+}
+
+// ===== OUTPUT FUNCTIONS ==========================================================================
+
+// log() and plot() calls are queued in the registry like the orders.  Rust writes them out with
+// the time of the tick they were made on, see Strategy::collect_output().
+
+// One log() call.  The strings belong to the interpreter and are only valid until
+// lua_bridge_clear_output() is called, as are plot_point's.
+struct log_message {
+    const char *level;
+    const char *message;
+};
+
+// One plot() call.  `chart` is NULL when no chart was given.
+struct plot_point {
+    const char *series;
+    double value;
+    const char *chart;
+};
+
+void queue_output(lua_State *L, const char *queue, int entry) {
+    lua_getfield(L, LUA_REGISTRYINDEX, queue);
+    lua_pushvalue(L, entry);
+    lua_rawseti(L, -2, lua_rawlen(L, -2) + 1);
+    lua_pop(L, 1);
+}
+
+// log(level, message) with level one of debug, info, warn or error
+int output_log(lua_State *L) {
+    static const char *levels[] = { "debug", "info", "warn", "error", NULL };
+
+    int level = luaL_checkoption(L, 1, NULL, levels);
+    luaL_checkstring(L, 2);
+
+    lua_newtable(L);
+
+    lua_pushstring(L, levels[level]);
+    lua_setfield(L, -2, "level");
+
+    lua_pushvalue(L, 2);
+    lua_setfield(L, -2, "message");
+
+    queue_output(L, "log_messages", lua_gettop(L));
+    return 0;
+}
+
+// plot(series, value[, chart]).  With a chart the value goes with its last completed candle,
+// otherwise with the tick.
+int output_plot(lua_State *L) {
+    luaL_checkstring(L, 1);
+    luaL_checknumber(L, 2);
+
+    if(!lua_isnoneornil(L, 3))
+        luaL_checkstring(L, 3);
+
+    lua_newtable(L);
+
+    lua_pushvalue(L, 1);
+    lua_setfield(L, -2, "series");
+
+    lua_pushvalue(L, 2);
+    lua_setfield(L, -2, "value");
+
+    if(!lua_isnoneornil(L, 3)) {
+        lua_pushvalue(L, 3);
+        lua_setfield(L, -2, "chart");
+    }
+
+    queue_output(L, "plot_points", lua_gettop(L));
+    return 0;
+}
+
+// called once Rust has copied the log messages and plot points
+void lua_bridge_clear_output(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "log_messages");
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "plot_points");
+}
+
+int lua_bridge_log_count(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "log_messages");
+    int count = (int)lua_rawlen(L, -1);
+    lua_pop(L, 1);
+
+    return count;
+}
+
+// `index` starts at 0
+void lua_bridge_get_log(lua_State *L, int index, struct log_message *out) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "log_messages");
+    lua_rawgeti(L, -1, index + 1);
+
+    lua_getfield(L, -1, "level");
+    out->level = lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "message");
+    out->message = lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    lua_pop(L, 2);
+}
+
+int lua_bridge_plot_count(lua_State *L) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "plot_points");
+    int count = (int)lua_rawlen(L, -1);
+    lua_pop(L, 1);
+
+    return count;
+}
+
+// `index` starts at 0
+void lua_bridge_get_plot(lua_State *L, int index, struct plot_point *out) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "plot_points");
+    lua_rawgeti(L, -1, index + 1);
+
+    lua_getfield(L, -1, "series");
+    out->series = lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "value");
+    out->value = lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    lua_getfield(L, -1, "chart");
+    out->chart = lua_isnil(L, -1) ? NULL : lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    lua_pop(L, 2);
+}
+
+// ===== SANDBOX FUNCTIONS =========================================================================
+
+// Strategy scripts run sandboxed (see lua_bridge_sandbox()) so a buggy one fails fast instead of
+// hanging or swapping an optimization that runs overnight.
+
+// the limited allocator's user data
+struct memory {
+    size_t used;
+    size_t limit;
+    int enforced; // only while the script runs, see protected_call()
+    int exceeded; // until the call that went over the limit returns
+};
+
+void * limited_alloc(void *ud, void *ptr, size_t osize, size_t nsize) {
+    struct memory *memory = (struct memory *)ud;
+
+    // without a block `osize` is the type of object being allocated
+    size_t old = ptr ? osize : 0;
+
+    if(nsize == 0) {
+        free(ptr);
+        memory->used -= old;
+        return NULL;
+    }
+
+    // Lua raises a memory error for the NULL, which the script could catch with pcall(), so
+    // every allocation after that fails as well
+    if(memory->enforced && nsize > old) {
+        if(memory->exceeded || memory->used + (nsize - old) > memory->limit) {
+            memory->exceeded = 1;
+            return NULL;
+        }
+    }
+
+    void *block = realloc(ptr, nsize);
+
+    if(block)
+        memory->used = memory->used - old + nsize;
+
+    return block;
+}
+
+// NULL when the state's memory isn't limited
+struct memory * limited_memory(lua_State *L) {
+    void *ud;
+
+    return lua_getallocf(L, &ud) == limited_alloc ? (struct memory *)ud : NULL;
+}
+
+// Runs once a call has gone through its whole budget, which protected_call() resets every time.
+// The script could catch the error with pcall() and carry on, so the limit is recorded in the
+// registry and the hook then runs on every instruction, raising the error again until the call
+// returns and fails (see protected_call()).  It raises the memory limit's error again the same
+// way.
+void instruction_hook(lua_State *L, lua_Debug *ar) {
+    struct memory *memory = limited_memory(L);
+
+    if(memory && memory->exceeded)
+        luaL_error(L, "memory limit of %d MB exceeded", (int)(memory->limit / 1024 / 1024));
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "limit_exceeded");
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+
+        lua_getfield(L, LUA_REGISTRYINDEX, "instruction_limit");
+        int limit = (int)lua_tointeger(L, -1);
+        lua_pop(L, 1);
+
+        luaL_where(L, 1);
+        lua_pushfstring(L, "instruction limit of %d exceeded, is there an infinite loop?", limit);
+        lua_concat(L, 2);
+
+        lua_pushvalue(L, -1);
+        lua_setfield(L, LUA_REGISTRYINDEX, "limit_exceeded");
+
+        lua_sethook(L, instruction_hook, LUA_MASKCOUNT, 1);
+    }
+
+    lua_error(L);
+}
+
+// Pushes the script's global `name`, from its own environment when it's sandboxed.
+void get_script_global(lua_State *L, const char *name) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "environment");
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+        lua_getglobal(L, name);
+        return;
+    }
+
+    lua_getfield(L, -1, name);
+    lua_remove(L, -2);
+}
+
+// defined with lua_bridge_setup()
+extern const luaL_Reg engine_functions[];
+
+// Copies the fields of the table at `from` named in `names`, which ends with NULL, to the table
+// at `to`.  Ones that don't exist, e.g. unpack() in a Lua built without 5.1 compatibility, are
+// left out.
+void copy_fields(lua_State *L, int from, int to, const char **names) {
+    from = lua_absindex(L, from);
+    to = lua_absindex(L, to);
+
+    for(; *names; names++) {
+        lua_getfield(L, from, *names);
+
+        if(lua_isnil(L, -1))
+            lua_pop(L, 1);
+        else
+            lua_setfield(L, to, *names);
+    }
+}
+
+// Has to be called before lua_bridge_load().  Unless `unsafe_libs` is set the script only gets
+// the libraries and functions listed below and the engine's, so it can't touch files, processes
+// or modules, or load code that isn't in the script.  Its globals go in a table of their own,
+// which reads through to the engine's (the functions above, the libraries and the registered
+// variables) so they can't be overwritten.  Every call is limited to `instruction_limit`
+// instructions and the state to `memory_limit` MB while the script runs (see protected_call()),
+// 0 meaning no limit.
+void lua_bridge_sandbox(lua_State *L, int unsafe_libs, int instruction_limit, int memory_limit) {
+    static const char *safe_globals[] = {
+        "assert", "error", "getmetatable", "ipairs", "next", "pairs", "pcall", "print",
+        "rawequal", "rawget", "rawlen", "rawset", "select", "setmetatable", "tonumber",
+        "tostring", "type", "unpack", "xpcall", "_VERSION",
+        "bit32", "coroutine", "math", "string", "table",
+        "account", // the engine's, see lua_bridge_setup()
+        NULL
+    };
+    static const char *safe_os[] = { "clock", "date", "difftime", "time", NULL };
+
+    ensure_initialized(L);
+
+    if(!unsafe_libs) {
+        lua_pushglobaltable(L);
+        int everything = lua_gettop(L);
+
+        lua_newtable(L);
+        int globals = lua_gettop(L);
+
+        copy_fields(L, everything, globals, safe_globals);
+
+        lua_pushvalue(L, globals);
+        luaL_setfuncs(L, engine_functions, 0);
+        lua_pop(L, 1);
+
+        lua_newtable(L);
+        lua_getfield(L, everything, "os");
+        copy_fields(L, -1, -2, safe_os);
+        lua_pop(L, 1);
+        lua_setfield(L, globals, "os");
+
+        // Strings share the string table as their metatable's __index, so s:dump() would still
+        // reach it from a copy
+        lua_getfield(L, globals, "string");
+        lua_pushnil(L);
+        lua_setfield(L, -2, "dump");
+        lua_pop(L, 1);
+
+        // The engine registers variables with lua_setglobal(), so they go in the new table too.
+        // The script can't reach the old one after this.
+        lua_pushvalue(L, globals);
+        lua_rawseti(L, LUA_REGISTRYINDEX, LUA_RIDX_GLOBALS);
+
+        lua_settop(L, everything - 1);
+    }
+
+    lua_newtable(L);
+    lua_newtable(L);
+
+    lua_pushglobaltable(L);
+    lua_setfield(L, -2, "__index");
+
+    lua_pushboolean(L, 0);
+    lua_setfield(L, -2, "__metatable");
+
+    lua_setmetatable(L, -2);
+
+    lua_pushvalue(L, -1);
+    lua_setfield(L, -2, "_G");
+
+    lua_setfield(L, LUA_REGISTRYINDEX, "environment");
+
+    lua_pushinteger(L, instruction_limit);
+    lua_setfield(L, LUA_REGISTRYINDEX, "instruction_limit");
+
+    if(memory_limit > 0) {
+        struct memory *memory = malloc(sizeof(struct memory));
+
+        if(!memory) {
+            fprintf(stderr, "\nCould not allocate the Lua memory limit\n");
+            exit(1);
+        }
+
+        // what the state has allocated so far
+        memory->used = (size_t)lua_gc(L, LUA_GCCOUNT, 0) * 1024 + lua_gc(L, LUA_GCCOUNTB, 0);
+        memory->limit = (size_t)memory_limit * 1024 * 1024;
+        memory->enforced = 0;
+        memory->exceeded = 0;
+
+        lua_setallocf(L, limited_alloc, memory);
+    }
+}
+
+// ===== ERROR FUNCTIONS ===========================================================================
+
+// Nothing exits on a Lua error: the functions below return non-zero and leave the message and
+// traceback in the registry for lua_bridge_error_message() and lua_bridge_error_traceback().
+
+void set_error(lua_State *L, const char *message, const char *traceback) {
+    lua_pushstring(L, message);
+    lua_setfield(L, LUA_REGISTRYINDEX, "error_message");
+
+    lua_pushstring(L, traceback);
+    lua_setfield(L, LUA_REGISTRYINDEX, "error_traceback");
+}
+
+// for errors found by the bridge rather than raised by the script, so there's no traceback
+int fail(lua_State *L, const char *message) {
+    set_error(L, message, "");
+    return 1;
+}
+
+// Message handler for lua_pcall().  Like xpcall(f, debug.traceback), but the message is kept
+// separately from the traceback.
+int traceback(lua_State *L) {
+    const char *message = lua_tostring(L, 1);
+
+    if(!message)
+        message = lua_pushfstring(L, "(error object is a %s value)", luaL_typename(L, 1));
+
+    lua_pushstring(L, message);
+    lua_setfield(L, LUA_REGISTRYINDEX, "error_message");
+
+    luaL_traceback(L, L, NULL, 1);
+    return 1;
+}
+
+// Calls the function below the `nargs` arguments on top of the stack.  On an error nothing is
+// left on the stack.
+//
+// The memory limit only applies while the call runs.  The engine's own pushes (the tick and
+// account tables, charts, ...) happen outside lua_pcall(), where a failed allocation would panic
+// and abort the whole process rather than fail one simulation.  A script that stays over the
+// limit still fails on its next allocation.
+//
+// A call that went over either limit fails even when the script caught the error with pcall().
+int protected_call(lua_State *L, int nargs, int nresults) {
+    int base = lua_gettop(L) - nargs;
+    struct memory *memory = limited_memory(L);
+    int was_enforced = memory ? memory->enforced : 0;
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "instruction_limit");
+    int instruction_limit = (int)lua_tointeger(L, -1);
+    lua_pop(L, 1);
+
+    // sets the budget back to the whole limit
+    if(instruction_limit > 0)
+        lua_sethook(L, instruction_hook, LUA_MASKCOUNT, instruction_limit);
+
+    lua_pushnil(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "limit_exceeded");
+
+    lua_pushcfunction(L, traceback);
+    lua_insert(L, base);
+
+    if(memory) {
+        memory->enforced = 1;
+        memory->exceeded = 0;
+    }
+
+    int status = lua_pcall(L, nargs, nresults, base);
+
+    if(memory)
+        memory->enforced = was_enforced;
+
+    lua_remove(L, base);
+
+    // The message handler isn't called for memory errors.  The garbage of the failed call is
+    // collected once the error is recorded.
+    if(status == LUA_ERRMEM || (memory && memory->exceeded)) {
+        lua_settop(L, base - 1);
+
+        if(memory) {
+            char err[255];
+            snprintf(err, sizeof(err), "memory limit of %lu MB exceeded",
+                     (unsigned long)(memory->limit / 1024 / 1024));
+
+            fail(L, err);
+            lua_gc(L, LUA_GCCOLLECT, 0);
+        } else {
+            fail(L, "not enough memory");
+        }
+
+        return 1;
+    }
+
+    if(status) {
+        lua_setfield(L, LUA_REGISTRYINDEX, "error_traceback");
+        return 1;
+    }
+
+    // the script caught the instruction limit's error
+    lua_getfield(L, LUA_REGISTRYINDEX, "limit_exceeded");
+
+    if(!lua_isnil(L, -1)) {
+        const char *message = lua_tostring(L, -1);
+        lua_settop(L, base - 1);
+
+        lua_pushstring(L, message);
+        lua_setfield(L, LUA_REGISTRYINDEX, "error_message");
+
+        lua_pushstring(L, "");
+        lua_setfield(L, LUA_REGISTRYINDEX, "error_traceback");
+
+        return 1;
+    }
+
+    lua_pop(L, 1);
+
     return 0;
 }
 
-int lua_get_decision() {
-    return trading_decision;
+char * lua_bridge_error_message(lua_State *L) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "error_message");
+    char *message = (char *)lua_tostring(L, -1);
+    lua_pop(L, 1); // the registry still holds the string
+
+    return message;
 }
 
-void bail(lua_State *L, char *msg) {
-    fprintf(stderr, "\nFATAL ERROR IN LUA:\n  %s: %s\n\n", msg, lua_tostring(L, -1));
-    exit(1);
+char * lua_bridge_error_traceback(lua_State *L) {
+    lua_getfield(L, LUA_REGISTRYINDEX, "error_traceback");
+    char *traceback = (char *)lua_tostring(L, -1);
+    lua_pop(L, 1);
+
+    return traceback;
 }
 
-void lua_bridge_setup(char *path) {
-    ensure_not_initialized();
+// ===== INTERPRETER FUNCTIONS =====================================================================
+
+// register_indicator(name, function(candles, state) ... return value, state end)
+int register_indicator(lua_State *L) {
+    luaL_checkstring(L, 1);
+    luaL_checktype(L, 2, LUA_TFUNCTION);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "indicators");
+    lua_pushvalue(L, 1);
+    lua_pushvalue(L, 2);
+    lua_rawset(L, -3);
+    lua_pop(L, 1);
+
+    return 0;
+}
+
+// the engine's functions, which sandboxed scripts get along with the safe_globals
+const luaL_Reg engine_functions[] = {
+    {"buy", order_buy},
+    {"sell", order_sell},
+    {"close", order_close},
+    {"close_all", order_close_all},
+    {"modify", order_modify},
+    {"open_long_trade", open_long_trade},
+    {"open_short_trade", open_short_trade},
+    {"close_trade", close_trade},
+    {"get_nanoseconds", get_nanoseconds},
+    {"register_indicator", register_indicator},
+    {"positions", positions},
+    {"log", output_log},
+    {"plot", output_plot},
+    {NULL, NULL}
+};
+
+lua_State * lua_bridge_setup() {
+    lua_State *L = luaL_newstate();
+
+    if(!L) {
+        fprintf(stderr, "\nCould not allocate a Lua state\n");
+        exit(1);
+    }
 
-    L = luaL_newstate();
     luaL_openlibs(L);
 
     // TODO: No performance benefit from this... yet.
@@ -90,61 +1178,160 @@ void lua_bridge_setup(char *path) {
 
     // register C functions
 
-    lua_pushcfunction(L, open_long_trade);
-    lua_setglobal(L, "open_long_trade");
+    lua_pushglobaltable(L);
+    luaL_setfuncs(L, engine_functions, 0);
+    lua_pop(L, 1);
+
+    // The account global is an empty proxy reading from the registry's table, so the strategy
+    // can't write to it.
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "account");
+
+    lua_newtable(L);
+    lua_newtable(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "account");
+    lua_setfield(L, -2, "__index");
+
+    lua_pushcfunction(L, read_only);
+    lua_setfield(L, -2, "__newindex");
+
+    lua_pushboolean(L, 0);
+    lua_setfield(L, -2, "__metatable");
+
+    lua_setmetatable(L, -2);
+    lua_setglobal(L, "account");
+
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "positions");
 
-    lua_pushcfunction(L, open_short_trade);
-    lua_setglobal(L, "open_short_trade");
+    // the callbacks' tick and ctx, see lua_bridge_set_tick()
 
-    lua_pushcfunction(L, close_trade);
-    lua_setglobal(L, "close_trade");
+    lua_createtable(L, 0, 13);
+    lua_setfield(L, LUA_REGISTRYINDEX, "tick");
 
-    lua_pushcfunction(L, get_nanoseconds);
-    lua_setglobal(L, "get_nanoseconds");
+    lua_createtable(L, 0, 10);
+    lua_setfield(L, LUA_REGISTRYINDEX, "context");
 
-    // seed PRNG with nanoseconds
+    // registered indicator functions and the state each indicator returned last time
 
-    luaL_dostring(L, "math.randomseed(get_nanoseconds)");
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "indicators");
 
-    // load .lua script
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "indicator_states");
 
-    if(luaL_loadfile(L, path))
-        bail(L, "luaL_loadfile() failed");
+    // orders placed during the current tick, see lua_bridge_get_order()
 
-    if(lua_pcall(L, 0, 0, 0))
-        bail(L, "lua_pcall() failed");
+    lua_newtable(L);
+    lua_setfield(L, LUA_REGISTRYINDEX, "orders");
 
+    lua_pushinteger(L, 1);
+    lua_setfield(L, LUA_REGISTRYINDEX, "next_trade_id");
+
+    // log() and plot() calls since they were last written out
+
+    lua_bridge_clear_output(L);
+
+    set_error(L, "", "");
+
+    // math.random() with a state of its own, see lua_bridge_seed_random()
+
+    lua_newuserdata(L, sizeof(unsigned long long));
+    lua_setfield(L, LUA_REGISTRYINDEX, "random_state");
+
+    set_random_seed(L, 0);
+
+    lua_getglobal(L, "math");
+
+    lua_pushcfunction(L, random_number);
+    lua_setfield(L, -2, "random");
+
+    lua_pushcfunction(L, random_seed);
+    lua_setfield(L, -2, "randomseed");
+
+    lua_pop(L, 1);
+
+    return L;
 }
 
-void lua_bridge_register_string(char *name, char *value) {
-    ensure_initialized();
+// loads and runs the .lua script
+int lua_bridge_load(lua_State *L, char *path) {
+    ensure_initialized(L);
+
+    if(luaL_loadfile(L, path)) {
+        fail(L, lua_tostring(L, -1));
+        lua_pop(L, 1);
+        return 1;
+    }
+
+    // a main chunk's only upvalue is _ENV
+    lua_getfield(L, LUA_REGISTRYINDEX, "environment");
+
+    if(lua_isnil(L, -1))
+        lua_pop(L, 1);
+    else
+        lua_setupvalue(L, -2, 1);
+
+    return protected_call(L, 0, 0);
+}
+
+void lua_bridge_register_string(lua_State *L, char *name, char *value) {
+    ensure_initialized(L);
     lua_pushstring(L, value);
     lua_setglobal(L, name);
 }
 
-void lua_bridge_register_number(char *name, float value) {
-    ensure_initialized();
+void lua_bridge_register_number(lua_State *L, char *name, float value) {
+    ensure_initialized(L);
     lua_pushnumber(L, value);
     lua_setglobal(L, name);
 }
 
-void lua_bridge_register_boolean(char *name, int value) {
-    ensure_initialized();
+void lua_bridge_register_integer(lua_State *L, char *name, int value) {
+    ensure_initialized(L);
+    lua_pushinteger(L, value);
+    lua_setglobal(L, name);
+}
+
+void lua_bridge_register_boolean(lua_State *L, char *name, int value) {
+    ensure_initialized(L);
     lua_pushboolean(L, value);
     lua_setglobal(L, name);
 }
 
-void lua_bridge_on_tick() {
-    ensure_initialized();
+// ===== CALLBACK FUNCTIONS ========================================================================
+
+// The strategy's callbacks (on_tick, on_candle_close, ...) are all optional.
+
+int lua_bridge_has_function(lua_State *L, char *name) {
+    ensure_initialized(L);
+
+    get_script_global(L, name);
+    int is_function = lua_isfunction(L, -1);
+    lua_pop(L, 1);
+
+    return is_function;
+}
+
+// Calls the global function `name` with the `nargs` values on top of the stack, which are
+// popped whether or not the strategy defines it.
+int lua_bridge_call(lua_State *L, char *name, int nargs) {
+    ensure_initialized(L);
+
+    get_script_global(L, name);
 
-    trading_decision = NOOP;
+    if(!lua_isfunction(L, -1)) {
+        lua_pop(L, nargs + 1);
+        return 0;
+    }
 
-    lua_getglobal(L, "on_tick");
-    if (lua_pcall(L, 0, 0, 0))
-        bail(L, "lua_pcall() failed");
+    lua_insert(L, -(nargs + 1));
+    return protected_call(L, nargs, 0);
 }
 
-void lua_bridge_print_vars() {
+void lua_bridge_print_vars(lua_State *L) {
     lua_getglobal(L, "booltest");
     lua_getglobal(L, "floattest");
     lua_getglobal(L, "inttest");
@@ -166,76 +1353,287 @@ void lua_bridge_print_vars() {
     }
 }
 
-void lua_bridge_teardown() {
-    ensure_initialized();
+void lua_bridge_teardown(lua_State *L) {
+    ensure_initialized(L);
+
+    // the limited allocator's count outlives the state
+    struct memory *memory = limited_memory(L);
+
     lua_close(L);
-    L = 0;
+    free(memory);
+}
+
+int lua_bridge_open_config(lua_State *L, char *path) {
+    return lua_bridge_load(L, path);
 }
 
-void lua_bridge_open_config(char *path) {
-    ensure_initialized();
+// The string stays on the stack so the pointer written to `value` stays valid.
+int lua_bridge_get_string_var(lua_State *L, char *name, char **value) {
+    ensure_initialized(L);
 
-    if(luaL_loadfile(L, path) || lua_pcall(L, 0, 0, 0)) {
-        bail(L, "failed to load config file");
+    get_script_global(L, name);
+
+    if(!lua_isstring(L, -1)) {
+        char err[255];
+        snprintf(err, sizeof(err), "'%s' should be a valid string name", name);
+        lua_pop(L, 1);
+        return fail(L, err);
     }
+
+    *value = (char *)lua_tostring(L, -1);
+    return 0;
 }
 
-char * lua_bridge_get_string_var(char *name) {
-    ensure_initialized();
+int lua_bridge_get_int_var(lua_State *L, char *name, int *value) {
+    ensure_initialized(L);
 
-    lua_getglobal(L, name);
+    get_script_global(L, name);
+
+    if(!lua_isnumber(L, -1)) {
+        char err[255];
+        snprintf(err, sizeof(err), "'%s' should be a valid integer name", name);
+        lua_pop(L, 1);
+        return fail(L, err);
+    }
+
+    *value = (int)lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    return 0;
+}
+
+// writes NULL to `value` when the variable isn't set at all
+int lua_bridge_get_optional_string_var(lua_State *L, char *name, char **value) {
+    ensure_initialized(L);
+
+    get_script_global(L, name);
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+        *value = NULL;
+        return 0;
+    }
 
     if(!lua_isstring(L, -1)) {
         char err[255];
-        sprintf(err, "'%s' should be a valid string name", name);
-        bail(L, err);
+        snprintf(err, sizeof(err), "'%s' should be a valid string name", name);
+        lua_pop(L, 1);
+        return fail(L, err);
     }
 
-    return (char *)lua_tostring(L, -1);
+    *value = (char *)lua_tostring(L, -1);
+    return 0;
 }
 
-int lua_bridge_get_int_var(char *name) {
-    ensure_initialized();
+// writes `fallback` to `value` when the variable isn't set at all
+int lua_bridge_get_optional_int_var(lua_State *L, char *name, int fallback, int *value) {
+    ensure_initialized(L);
 
-    lua_getglobal(L, name);
+    get_script_global(L, name);
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+        *value = fallback;
+        return 0;
+    }
 
     if(!lua_isnumber(L, -1)) {
         char err[255];
-        sprintf(err, "'%s' should be a valid integer name", name);
-        bail(L, err);
+        snprintf(err, sizeof(err), "'%s' should be a valid integer name", name);
+        lua_pop(L, 1);
+        return fail(L, err);
+    }
+
+    *value = (int)lua_tonumber(L, -1);
+    lua_pop(L, 1);
+
+    return 0;
+}
+
+// writes `fallback` to `value` when the variable isn't set at all
+int lua_bridge_get_optional_bool_var(lua_State *L, char *name, int fallback, int *value) {
+    ensure_initialized(L);
+
+    get_script_global(L, name);
+
+    if(lua_isnil(L, -1)) {
+        lua_pop(L, 1);
+        *value = fallback;
+        return 0;
+    }
+
+    if(!lua_isboolean(L, -1)) {
+        char err[255];
+        snprintf(err, sizeof(err), "'%s' should be true or false", name);
+        lua_pop(L, 1);
+        return fail(L, err);
+    }
+
+    *value = lua_toboolean(L, -1);
+    lua_pop(L, 1);
+
+    return 0;
+}
+
+// ===== CONFIG FUNCTIONS ==========================================================================
+
+// For reading nested config tables from Rust (see config_tables.rs).  Each push returns the
+// pushed value's type (LUA_TNIL, LUA_TNUMBER, ...) and is matched by a lua_bridge_pop().  The
+// others read the value on top of the stack.
+
+int lua_bridge_push_global(lua_State *L, char *name) {
+    ensure_initialized(L);
+
+    get_script_global(L, name);
+    return lua_type(L, -1);
+}
+
+int lua_bridge_push_field(lua_State *L, char *name) {
+    ensure_initialized(L);
+
+    lua_getfield(L, -1, name);
+    return lua_type(L, -1);
+}
+
+// `index` starts at 1, as in Lua
+int lua_bridge_push_index(lua_State *L, int index) {
+    ensure_initialized(L);
+
+    lua_rawgeti(L, -1, index);
+    return lua_type(L, -1);
+}
+
+void lua_bridge_pop(lua_State *L) {
+    ensure_initialized(L);
+    lua_pop(L, 1);
+}
+
+// of the table's array part, i.e. #t
+int lua_bridge_length(lua_State *L) {
+    ensure_initialized(L);
+    return (int)lua_rawlen(L, -1);
+}
+
+double lua_bridge_number(lua_State *L) {
+    ensure_initialized(L);
+    return lua_tonumber(L, -1);
+}
+
+// only valid while the value is on the stack
+const char * lua_bridge_string(lua_State *L) {
+    ensure_initialized(L);
+    return lua_tostring(L, -1);
+}
+
+int lua_bridge_boolean(lua_State *L) {
+    ensure_initialized(L);
+    return lua_toboolean(L, -1);
+}
+
+// The table's `n`th string key, starting at 0, or NULL past the last one.  The order is
+// arbitrary.  The key stays valid while the table is on the stack.
+const char * lua_bridge_string_key(lua_State *L, int n) {
+    const char *key = NULL;
+
+    ensure_initialized(L);
+
+    lua_pushnil(L);
+
+    while(lua_next(L, -2)) {
+        lua_pop(L, 1); // the value
+
+        if(lua_type(L, -1) == LUA_TSTRING && n-- == 0) {
+            key = lua_tostring(L, -1);
+            lua_pop(L, 1);
+            break;
+        }
     }
 
-    return (int)lua_tonumber(L, -1);
+    return key;
 }
 
 // ===== TABLE FUNCTIONS ===========================================================================
 
-void lua_bridge_create_table(int size) {
-    ensure_initialized();
+void lua_bridge_create_table(lua_State *L, int size) {
+    ensure_initialized(L);
     lua_createtable(L, size, 0);
 }
 
-void lua_bridge_push_table_integer(int num) {
-    ensure_initialized();
+void lua_bridge_push_table_integer(lua_State *L, int num) {
+    ensure_initialized(L);
     lua_pushinteger(L, num);
 }
 
-void lua_bridge_push_table_number(float num) {
-    ensure_initialized();
+void lua_bridge_push_table_number(lua_State *L, float num) {
+    ensure_initialized(L);
     lua_pushnumber(L, num);
 }
 
-void lua_bridge_push_table_string(char *name) {
-    ensure_initialized();
+void lua_bridge_push_table_string(lua_State *L, char *name) {
+    ensure_initialized(L);
     lua_pushstring(L, name);
 }
 
-void lua_bridge_set_table(int offset) {
-    ensure_initialized();
+void lua_bridge_push_table_boolean(lua_State *L, int value) {
+    ensure_initialized(L);
+    lua_pushboolean(L, value);
+}
+
+void lua_bridge_set_table(lua_State *L, int offset) {
+    ensure_initialized(L);
     lua_rawset(L, offset);
 }
 
-void lua_bridge_finalize_table(char *name) {
-    ensure_initialized();
+void lua_bridge_finalize_table(lua_State *L, char *name) {
+    ensure_initialized(L);
     lua_setglobal(L, name);
 }
+
+// ===== INDICATOR FUNCTIONS =======================================================================
+
+int lua_bridge_call_indicator(lua_State *L,
+                              char *function,
+                              char *chart,
+                              char *indicator,
+                              float *value) {
+    ensure_initialized(L);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "indicators");
+    lua_getfield(L, -1, function);
+
+    if(!lua_isfunction(L, -1)) {
+        char err[255];
+        snprintf(err, sizeof(err), "no indicator function registered as '%s'", function);
+        lua_pop(L, 2);
+        return fail(L, err);
+    }
+
+    lua_getglobal(L, chart);
+
+    lua_getfield(L, LUA_REGISTRYINDEX, "indicator_states");
+    lua_getfield(L, -1, indicator);
+    lua_remove(L, -2);
+
+    // stack: indicators, function, candles, state
+    if(protected_call(L, 2, 2)) {
+        lua_pop(L, 1);
+        return 1;
+    }
+
+    if(!lua_isnumber(L, -2)) {
+        char err[255];
+        snprintf(err, sizeof(err), "indicator function '%s' must return a number", function);
+        lua_pop(L, 3);
+        return fail(L, err);
+    }
+
+    *value = (float)lua_tonumber(L, -2);
+
+    // stack: indicators, value, state
+    lua_getfield(L, LUA_REGISTRYINDEX, "indicator_states");
+    lua_pushvalue(L, -2);
+    lua_setfield(L, -2, indicator);
+    lua_pop(L, 4);
+
+    return 0;
+}
@@ -32,7 +32,7 @@ fn main() {
 
     let lua_path = args[1].as_slice();
 
-    ConfigurationFile::load_from_file(lua_path);
+    config::expect_valid(ConfigurationFile::load_from_file(lua_path));
 
     let csv_path           = config::get().csv_path.as_slice();
     let in_sample_days     = utilities::config_time_to_days(config::get().in_sample.as_slice());
@@ -50,6 +50,7 @@ fn main() {
     let mut ticks_processed_by_charts = 0i32;
 
     println!("Simulating a maximum of {} steps", max_steps);
+    println!("Seed: {} (set SEED to this to replay the run)", config::get().seed);
 
     let mut file = utilities::buf_reader_from_file(csv_path, 0);
     let mut bytes_read: uint = 0;
@@ -58,8 +59,9 @@ fn main() {
 
     println!("==================== FILLING CHARTS ====================");
 
-    let chart_string = config::get().charts.clone();
-    let mut charts: Vec<Chart> = parsers::parse_charts_from_string(chart_string);
+    // already checked along with the variables when the config was loaded
+    let mut charts: Vec<Chart> =
+        config::expect_valid(parsers::build_charts(&config::get().charts, true));
 
     for mut line in file.lines().filter_map( |l| l.ok() ) {
         bytes_read += line.len();
@@ -67,20 +69,20 @@ fn main() {
         let tick = Tick::new_from_line(line);
 
         for chart in charts.iter_mut() {
-            chart.process_tick(&tick);
+            chart.process_tick(&tick, None).ok().unwrap();
             ticks_processed_by_charts += 1;
         }
 
-        let mut all_charts_have_data = true;
+        let mut all_charts_ready = true;
 
-        for chart in charts.iter_mut() {
-            if !chart.has_full_data() {
-                all_charts_have_data = false;
+        for chart in charts.iter() {
+            if !chart.is_ready() {
+                all_charts_ready = false;
                 break;
             }
         }
 
-        if all_charts_have_data {
+        if all_charts_ready {
             break;
         }
     }
@@ -100,7 +102,7 @@ fn main() {
         let tick = Tick::new_from_line(line);
 
         for chart in charts.iter_mut() {
-            chart.process_tick(&tick);
+            chart.process_tick(&tick, None).ok().unwrap();
             ticks_processed_by_charts += 1;
         }
 
@@ -133,15 +135,34 @@ fn main() {
 
     let trade_log_path = &Path::new("output/trades.csv");
     let ticks_log_path = &Path::new("output/ticks.csv");
+    let chart_log_path = &Path::new("output/charts.csv");
+    let run_log_path   = &Path::new("output/run.csv");
 
     let _ = fs::unlink(trade_log_path);
     let _ = fs::unlink(ticks_log_path);
+    let _ = fs::unlink(chart_log_path);
+    let _ = fs::unlink(run_log_path);
 
     let mut trades_log = File::create(trade_log_path).ok().unwrap();
     let mut ticks_log  = File::create(ticks_log_path).ok().unwrap();
+    let mut charts_log = File::create(chart_log_path).ok().unwrap();
 
     Trade::write_csv_header(&mut trades_log);
     Tick::write_csv_header(&mut ticks_log);
+    Chart::write_csv_header(&mut charts_log);
+
+    // what the run needs to be replayed, set SEED to the seed
+    let mut run_log = File::create(run_log_path).ok().unwrap();
+
+    let run = format!(
+        "seed,config,csv_path,started\n{},{},{},{}\n",
+        config::get().seed,
+        lua_path,
+        csv_path,
+        utilities::tm_to_iso(time::now_utc())
+    );
+
+    run_log.write(run.as_bytes()).ok().unwrap();
 
     // ----- MAIN LOOP -----------------------------------------------------------------------------
 
@@ -157,7 +178,7 @@ fn main() {
 
     for i in range(1i32, max_steps + 1) {
         let strategy = Strategy::new(lua_path);
-        let optimizer = Optimizer::new(strategy.clone());
+        let mut optimizer = Optimizer::new(strategy.clone(), i);
 
         let mut first_tick = true;
 
@@ -183,7 +204,7 @@ fn main() {
                 let tick = Tick::new_from_line(line);
 
                 for chart in pristine_charts.iter_mut() {
-                    chart.process_tick(&tick);
+                    chart.process_tick(&tick, None).ok().unwrap();
                     ticks_processed_by_charts += 1;
                 }
 
@@ -267,7 +288,13 @@ fn main() {
         println!("==================== OPTIMIZING ====================");
         // println!("Next tick: {}", utilities::tm_to_iso(Tick::new_from_line(file.read_line().ok().unwrap()).time));
 
-        let vars = match optimizer.variables_for(charts.clone(), &ticks, &mut trades_log, &mut ticks_log) {
+        let vars = match optimizer.variables_for(
+            charts.clone(),
+            &ticks,
+            &mut trades_log,
+            &mut ticks_log,
+            &mut charts_log,
+        ) {
             Some(vars) => vars,
             None       => {
                 failed_to_optimize_algorithm = true;
@@ -293,7 +320,7 @@ fn main() {
             let tick = Tick::new_from_line(line);
 
             for chart in charts.iter_mut() {
-                chart.process_tick(&tick);
+                chart.process_tick(&tick, None).ok().unwrap();
             }
 
             if bytes_read == bytes_read_at_out_of_sample {
@@ -346,9 +373,20 @@ fn main() {
 
         let strat = strategy.clone();
         let mut algorithm = Algorithm::new_out_of_sample(strat, charts.clone());
-        let score = match algorithm.execute_on(&ticks, vars.clone(), &mut trades_log, &mut ticks_log) {
-            Some(score) => score,
-            None        => {
+        let score = match algorithm.execute_on(
+            &ticks,
+            vars.clone(),
+            &mut trades_log,
+            &mut ticks_log,
+            &mut charts_log,
+        ) {
+            Ok(Some(score)) => score,
+            Ok(None)        => {
+                failed_to_execute = true;
+                break
+            },
+            Err(error)      => {
+                error.print();
                 failed_to_execute = true;
                 break
             },
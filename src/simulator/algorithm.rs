@@ -1,10 +1,14 @@
+extern crate time;
+
 use std::io::File;
 
 use chart::Chart;
+use lua::LuaError;
 use range_bound_variable::RangeBoundVariables;
 use simulation::Simulation;
 use strategy::Strategy;
 use tick::Tick;
+use trade::ExitReason;
 
 pub struct Algorithm {
     simulation: Simulation,
@@ -12,18 +16,46 @@ pub struct Algorithm {
     in_sample: bool,
 }
 
+// fills in where a simulation failed
+fn locate_error(mut error: LuaError, tick: Option<&Tick>, vars: &RangeBoundVariables) -> LuaError {
+    error.tick_time = tick.map( |t| t.time );
+    error.variables = Some(vars.clone());
+    error
+}
+
 impl Algorithm {
+    // Returns the score, or None when the drawdown limit was hit.  A Lua error ends the
+    // simulation without logging anything.
     pub fn execute_on(&mut self,
                       ticks: &Vec<Tick>,
                       vars: RangeBoundVariables,
                       tradefile: &mut File,
-                      tickfile: &mut File) -> Option<f32> {
+                      tickfile: &mut File,
+                      chartfile: &mut File) -> Result<Option<f32>, LuaError> {
         let mut tick_count = 0i32;
         let mut exceeded_drawdown_limit = false;
 
+        // spent in the strategy's callbacks, publishing tick and ctx included
+        let mut strategy_ns = 0u64;
+
         let ref mut sim = self.simulation;
 
-        self.strategy.setup(vars);
+        // a sample that doesn't work for an indicator fails only this run
+        match sim.bind_variables(&vars) {
+            Ok(())       => {},
+            Err(message) => return Err(locate_error(LuaError::new(message), None, &vars)),
+        }
+
+        let lua = match self.strategy.setup(vars.clone(), sim.get_id()) {
+            Ok(lua)    => lua,
+            Err(error) => return Err(locate_error(error, None, &vars)),
+        };
+
+        match self.strategy.on_start(&lua, &vars) {
+            Ok(())     => {},
+            Err(error) => return Err(locate_error(error, None, &vars)),
+        }
+
         sim.activate_charts();
 
         let mut last_tick = &Tick::empty_tick();
@@ -32,33 +64,68 @@ impl Algorithm {
             tick_count += 1;
 
             sim.record_tick_onto_trades(tick);
-            sim.update_charts(tick);
+            let completed_charts = match sim.update_charts(tick, &lua) {
+                Ok(names)  => names,
+                Err(error) => return Err(locate_error(error, Some(tick), &vars)),
+            };
+
+            sim.update_tick_statistics(tick);
 
             sim.update_drawdown();
             if sim.has_exceeded_max_drawdown() {
                 exceeded_drawdown_limit = true;
                 break;
             }
-            // TODO: self.simulation.process_stops();
+
             // TODO: in pre-tick SL/TP checks, make sure FIFO is not violated
+            sim.process_stops(tick);
 
             if sim.can_trade() {
-                self.strategy.on_tick(sim, tick);
+                let started = time::precise_time_ns();
+
+                let mut result = self.strategy.process_trade_events(&lua, sim, tick);
+
+                for name in completed_charts.iter() {
+                    if result.is_ok() {
+                        result = self.strategy.on_candle_close(&lua, sim, tick, name.as_slice());
+                    }
+                }
+
+                if result.is_ok() {
+                    result = self.strategy.on_tick(&lua, sim, tick);
+                }
+
+                strategy_ns += time::precise_time_ns() - started;
+
+                match result {
+                    Ok(())     => {},
+                    Err(error) => return Err(locate_error(error, Some(tick), &vars)),
+                }
             }
 
             last_tick = tick;
         }
 
         // TODO: BUG. This will record the last tick again onto the trades.
-        sim.close_all_open_trades(last_tick);
-        self.strategy.teardown();
+        sim.close_all_open_trades(last_tick, match exceeded_drawdown_limit {
+            true  => ExitReason::DrawdownLimit,
+            false => ExitReason::EndOfSimulation,
+        });
+
+        match self.strategy.on_finish(&lua, sim, last_tick, tick_count) {
+            Ok(())     => {},
+            Err(error) => return Err(locate_error(error, Some(last_tick), &vars)),
+        }
+
+        drop(lua);
 
         sim.log_trades(tradefile);
         sim.log_ticks(tickfile);
+        sim.log_charts(chartfile);
 
         if exceeded_drawdown_limit {
                 println!("Exceeded max drawdown, aborting simulation");
-                None
+                Ok(None)
         } else {
             println!(
                 "Final score: {:.1} - Profit: {:.1} - Total trades: {}/{}",
@@ -68,12 +135,13 @@ impl Algorithm {
                 sim.closed_short_trade_count(),
             );
 
-            println!("Ticks processed: {} - Max DD: {:.2}%",
+            println!("Ticks processed: {} - Max DD: {:.2}% - Strategy: {:.2}us per tick",
                 tick_count,
                 sim.get_highest_drawdown(),
+                strategy_ns as f64 / 1000.0 / tick_count as f64,
             );
 
-            Some(sim.pip_expectancy())
+            Ok(Some(sim.pip_expectancy()))
         }
     }
 
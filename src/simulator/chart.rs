@@ -2,9 +2,15 @@
 // If the config file specifies 60 periods, they will be FULL candles indexed from 1-60.
 // Index 0 will be the current period's incomplete candle.
 
-use lua;
-use indicators::Indicator;
+extern crate time;
+
+use std::io::File;
+
+use indicators::ChartIndicator;
+use lua::{Lua, LuaError};
+use range_bound_variable::RangeBoundVariables;
 use tick::Tick;
+use utilities;
 
 #[derive(Clone,Copy)]
 pub enum ChartPeriod {
@@ -24,15 +30,15 @@ pub enum ChartType {
 
 // ===== GLOBAL FUNCTIONS ==========================================================================
 
-fn period_from_string(s: &str) -> ChartPeriod {
+pub fn period_from_string(s: &str) -> Option<ChartPeriod> {
     match s {
-        "M1"  => ChartPeriod::M1,
-        "M5"  => ChartPeriod::M5,
-        "M15" => ChartPeriod::M15,
-        "M30" => ChartPeriod::M30,
-        "H1"  => ChartPeriod::H1,
-        "H4"  => ChartPeriod::H4,
-        _     => panic!("Unknown chart period: {}", s)
+        "M1"  => Some(ChartPeriod::M1),
+        "M5"  => Some(ChartPeriod::M5),
+        "M15" => Some(ChartPeriod::M15),
+        "M30" => Some(ChartPeriod::M30),
+        "H1"  => Some(ChartPeriod::H1),
+        "H4"  => Some(ChartPeriod::H4),
+        _     => None,
     }
 }
 
@@ -47,6 +53,31 @@ fn seconds_in_period(period: ChartPeriod) -> i32 {
     }
 }
 
+// orders indicators depth first so each one's input is updated before it is
+fn visit_indicator(index: uint,
+                   dependencies: &Vec<Option<uint>>,
+                   names: &Vec<String>,
+                   states: &mut Vec<uint>,
+                   order: &mut Vec<uint>) -> Result<(), String> {
+    match states[index] {
+        2 => return Ok(()),
+        1 => return Err(format!("indicator inputs form a cycle at {}", names[index])),
+        _ => {},
+    }
+
+    states[index] = 1;
+
+    match dependencies[index] {
+        Some(dependency) => try!(visit_indicator(dependency, dependencies, names, states, order)),
+        None             => {},
+    }
+
+    states[index] = 2;
+    order.push(index);
+
+    Ok(())
+}
+
 // ===== CANDLE ====================================================================================
 
 #[derive(Clone,Copy,Show)]
@@ -66,8 +97,151 @@ pub struct Candle {
     id: i32,
 }
 
+impl Candle {
+    // a candle with the same bid and ask prices
+    pub fn from_prices(open: f32, high: f32, low: f32, close: f32) -> Candle {
+        Candle {
+            open_bid:  open,
+            open_ask:  open,
+            close_bid: close,
+            close_ask: close,
+
+            high_bid: high,
+            high_ask: high,
+            low_bid:  low,
+            low_ask:  low,
+
+            volume: 0,
+
+            id: 0,
+        }
+    }
+
+    // a candle whose prices are all `value`, used to feed one indicator's output to another
+    pub fn from_value(value: f32) -> Candle {
+        Candle::from_prices(value, value, value, value)
+    }
+
+    pub fn open(&self, side: PriceSide) -> f32 {
+        side.pick(self.open_bid, self.open_ask)
+    }
+
+    // the mid high/low is approximated from the bid and ask extremes, which may come from
+    // different ticks
+    pub fn high(&self, side: PriceSide) -> f32 {
+        side.pick(self.high_bid, self.high_ask)
+    }
+
+    pub fn low(&self, side: PriceSide) -> f32 {
+        side.pick(self.low_bid, self.low_ask)
+    }
+
+    pub fn close(&self, side: PriceSide) -> f32 {
+        side.pick(self.close_bid, self.close_ask)
+    }
+}
+
+// ===== PRICE SOURCE ==============================================================================
+
+#[derive(Clone,Copy,Show)]
+pub enum PriceSide {
+    Bid,
+    Ask,
+    Mid,
+}
+
+impl PriceSide {
+    pub fn pick(&self, bid: f32, ask: f32) -> f32 {
+        match *self {
+            PriceSide::Bid => bid,
+            PriceSide::Ask => ask,
+            PriceSide::Mid => (bid + ask) / 2.0,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Show)]
+pub enum PriceField {
+    Open,
+    High,
+    Low,
+    Close,
+    Median,   // (high + low) / 2
+    Typical,  // (high + low + close) / 3
+    Weighted, // (high + low + close * 2) / 4
+}
+
+// Which price of a candle an indicator reads, written as "#{field}_#{side}" in the chart
+// definition, e.g. "close_bid" (the default), "hlc3_mid" or "high_ask".
+#[derive(Clone,Copy,Show)]
+pub struct PriceSource {
+    pub field: PriceField,
+    pub side: PriceSide,
+}
+
+impl PriceSource {
+    pub fn close_bid() -> PriceSource {
+        PriceSource { field: PriceField::Close, side: PriceSide::Bid }
+    }
+
+    pub fn from_string(s: &str) -> Option<PriceSource> {
+        let parts: Vec<&str> = s.split('_').collect();
+
+        if 2 != parts.len() {
+            return None;
+        }
+
+        let field = match parts[0] {
+            "open"  => PriceField::Open,
+            "high"  => PriceField::High,
+            "low"   => PriceField::Low,
+            "close" => PriceField::Close,
+            "hl2"   => PriceField::Median,
+            "hlc3"  => PriceField::Typical,
+            "hlcc4" => PriceField::Weighted,
+            _       => return None,
+        };
+
+        let side = match parts[1] {
+            "bid" => PriceSide::Bid,
+            "ask" => PriceSide::Ask,
+            "mid" => PriceSide::Mid,
+            _     => return None,
+        };
+
+        Some(PriceSource { field: field, side: side })
+    }
+
+    pub fn value(&self, candle: &Candle) -> f32 {
+        let side = self.side;
+
+        match self.field {
+            PriceField::Open     => candle.open(side),
+            PriceField::High     => candle.high(side),
+            PriceField::Low      => candle.low(side),
+            PriceField::Close    => candle.close(side),
+            PriceField::Median   => (candle.high(side) + candle.low(side)) / 2.0,
+            PriceField::Typical  => {
+                (candle.high(side) + candle.low(side) + candle.close(side)) / 3.0
+            },
+            PriceField::Weighted => {
+                (candle.high(side) + candle.low(side) + candle.close(side) * 2.0) / 4.0
+            },
+        }
+    }
+}
+
 // ===== CHART =====================================================================================
 
+// One series' value on a completed candle, written to output/charts.csv in long format so
+// indicators with any number of outputs fit
+#[derive(Clone)]
+struct ChartExport {
+    time: i64, // when the candle opened
+    series: String,
+    value: f32,
+}
+
 #[derive(Clone)]
 pub struct Chart {
     candles: Vec<Candle>,
@@ -75,16 +249,22 @@ pub struct Chart {
     max_candles: i32,
     name: String,
     seconds_per_period: i32,
-    indicators: Vec<Indicator>,
+    indicators: Vec<ChartIndicator>,
     chart_type: ChartType,
     active: bool,
     ticks_processed: i32,
+
+    // recorded while the chart is active, i.e. during a simulation
+    exports: Vec<ChartExport>,
 }
 
 impl Chart {
     fn new(name: &str, period: &str, max_candles: i32, ct: ChartType) -> Chart {
         let candles: Vec<Candle> =  Vec::with_capacity(max_candles as uint);
-        let period_type = period_from_string(period);
+        let period_type = match period_from_string(period) {
+            Some(period_type) => period_type,
+            None              => panic!("Unknown chart period: {}", period),
+        };
 
         Chart {
             candles: candles,
@@ -96,6 +276,7 @@ impl Chart {
             chart_type: ct,
             active: false,
             ticks_processed: 0,
+            exports: vec!(),
         }
     }
 
@@ -103,11 +284,144 @@ impl Chart {
         Chart::new(name, period, max_candles, ChartType::Candlestick)
     }
 
-    pub fn attach_indicator(&mut self, indi: Indicator) {
+    pub fn attach_indicator(&mut self, indi: ChartIndicator) {
         self.indicators.push(indi)
     }
 
-    fn create_new_candle_from_tick(&mut self, id: i32, tick: &Tick) {
+    // Must be called once all the indicators are attached.  Finds the indicator behind each
+    // input name and sorts the indicators so inputs are always updated first.  Fails when an
+    // input doesn't exist or the inputs form a cycle.
+    pub fn resolve_indicator_inputs(&mut self) -> Result<(), String> {
+        let count = self.indicators.len();
+
+        let names: Vec<String> = self.indicators.iter()
+                                                .map( |i| i.get_name().to_string() )
+                                                .collect();
+        let mut dependencies: Vec<Option<uint>> = vec!();
+        let mut outputs: Vec<uint> = vec!();
+
+        for indicator in self.indicators.iter() {
+            let input_name = match indicator.get_input_name() {
+                Some(name) => name,
+                None       => {
+                    dependencies.push(None);
+                    outputs.push(0);
+                    continue;
+                },
+            };
+
+            let mut found: Option<(uint, uint)> = None;
+
+            for (index, candidate) in self.indicators.iter().enumerate() {
+                for output in range(0, candidate.output_suffixes().len()) {
+                    if candidate.output_name(output).as_slice() == input_name {
+                        found = Some((index, output));
+                    }
+                }
+            }
+
+            match found {
+                Some((index, output)) => {
+                    dependencies.push(Some(index));
+                    outputs.push(output);
+                },
+                None => return Err(format!(
+                    "{} uses unknown input {}",
+                    indicator.get_name(),
+                    input_name
+                )),
+            }
+        }
+
+        let mut states: Vec<uint> = Vec::from_elem(count, 0u);
+        let mut order: Vec<uint> = vec!();
+
+        for index in range(0, count) {
+            try!(visit_indicator(index, &dependencies, &names, &mut states, &mut order));
+        }
+
+        let unsorted = self.indicators.clone();
+        self.indicators = order.iter().map( |&index| unsorted[index].clone() ).collect();
+
+        for (new_index, &old_index) in order.iter().enumerate() {
+            let dependency = match dependencies[old_index] {
+                Some(dependency) => dependency,
+                None             => continue,
+            };
+
+            let input_index = order.iter().position( |&i| i == dependency ).unwrap();
+            self.indicators[new_index].set_input_index(input_index, outputs[old_index]);
+        }
+
+        self.retain_input_history();
+
+        Ok(())
+    }
+
+    // makes every input keep enough history for the indicators reading it
+    fn retain_input_history(&mut self) {
+        for index in range(0, self.indicators.len()) {
+            if !self.indicators[index].is_bound() {
+                continue;
+            }
+
+            match self.indicators[index].get_input_index() {
+                Some((input_index, _)) => {
+                    let required = self.indicators[index].candles_required() as uint;
+                    self.indicators[input_index].retain_history(required);
+                },
+                None => {},
+            }
+        }
+    }
+
+    // see ChartIndicator::check_variables()
+    pub fn check_variables(&self, vars: &RangeBoundVariables) -> Result<(), String> {
+        for indicator in self.indicators.iter() {
+            try!(indicator.check_variables(vars, self.max_candles - 1));
+        }
+
+        Ok(())
+    }
+
+    // Binds indicator parameters that reference range-bound variables.  Those indicators and
+    // every indicator reading another one start over and prime again from the candles.  Fails
+    // when the sampled values don't work for an indicator.
+    pub fn bind_variables(&mut self, vars: &RangeBoundVariables) -> Result<(), String> {
+        if !self.indicators.iter().any( |i| i.has_variables() ) {
+            return Ok(());
+        }
+
+        for indicator in self.indicators.iter_mut() {
+            // the rest don't depend on the variables and keep their state, which matters for
+            // ones built from ticks the chart can't replay (see indicators::levels)
+            if !indicator.has_variables() && indicator.get_input_name().is_none() {
+                continue;
+            }
+
+            try!(indicator.bind_variables(vars));
+
+            let required = indicator.candles_required();
+
+            if indicator.get_input_name().is_none() && required >= self.max_candles {
+                return Err(format!(
+                    "indicator {} needs {} candles but the chart only has {}",
+                    indicator.get_name(),
+                    required,
+                    self.max_candles - 1,
+                ));
+            }
+        }
+
+        self.retain_input_history();
+
+        Ok(())
+    }
+
+    fn create_new_candle_from_tick(&mut self,
+                                   id: i32,
+                                   tick: &Tick,
+                                   lua: Option<&Lua>) -> Result<(), LuaError> {
         if self.candles.len() as i32 >= self.max_candles {
             self.candles.pop();
         }
@@ -130,21 +444,132 @@ impl Chart {
 
         self.candles.insert(0, candle);
 
-        if self.has_full_data() {
-            for indicator in self.indicators.iter_mut() {
-                indicator.update(&self.candles, self.active);
+        // Lua indicators read the candle table, so it goes out first
+        match lua {
+            Some(lua) => self.send_to_lua(lua),
+            None      => {},
+        }
+
+        try!(self.update_indicators(lua));
+
+        if self.active && self.candles.len() > 1 {
+            self.record_exports();
+        }
+
+        Ok(())
+    }
+
+    // the candle that just completed along with every indicator's outputs
+    fn record_exports(&mut self) {
+        let candle = self.candles[1];
+        let time   = candle.id as i64 * self.seconds_per_period as i64;
+
+        let mut values: Vec<(String, f32)> = vec!(
+            ("open_bid".to_string(),  candle.open_bid),
+            ("high_bid".to_string(),  candle.high_bid),
+            ("low_bid".to_string(),   candle.low_bid),
+            ("close_bid".to_string(), candle.close_bid),
+        );
+
+        for indicator in self.indicators.iter() {
+            if !indicator.is_bound() {
+                continue;
+            }
+
+            for (index, &value) in indicator.get_values().iter().enumerate() {
+                values.push((indicator.output_name(index), value));
             }
         }
 
-        self.send_to_lua();
+        for (series, value) in values.into_iter() {
+            self.exports.push(ChartExport { time: time, series: series, value: value });
+        }
+    }
+
+    // A value the strategy plotted for the candle that completed last.  Nothing is recorded before
+    // the first candle completes.
+    pub fn record_plot(&mut self, series: &str, value: f32) {
+        if !self.active || self.candles.len() < 2 {
+            return;
+        }
+
+        let time = self.candles[1].id as i64 * self.seconds_per_period as i64;
+
+        self.exports.push(ChartExport { time: time, series: series.to_string(), value: value });
+    }
+
+    pub fn log_exports(&self, simulation_id: i32, logfile: &mut File) {
+        for export in self.exports.iter() {
+            let time = time::at_utc(time::Timespec::new(export.time, 0));
+
+            let s = format!(
+                "{},{},{},{},{}\n",
+                simulation_id,
+                self.name,
+                utilities::tm_to_iso(time),
+                export.series,
+                export.value,
+            );
+
+            logfile.write(s.as_bytes()).ok().unwrap();
+        }
+    }
+
+    pub fn write_csv_header(logfile: &mut File) {
+        logfile.write(b"simulation_id,chart,time,series,value\n").ok().unwrap();
+    }
+
+    fn update_indicators(&mut self, lua: Option<&Lua>) -> Result<(), LuaError> {
+        for index in range(0, self.indicators.len()) {
+            // waiting for the optimizer to pick values for its parameters
+            if !self.indicators[index].is_bound() {
+                continue;
+            }
+
+            let required = self.indicators[index].candles_required() as uint;
+
+            let (input_index, output) = match self.indicators[index].get_input_index() {
+                Some(input) => input,
+                None        => {
+                    // the incomplete candle at index 0 doesn't count towards the warm-up
+                    if self.candles.len() > required {
+                        try!(self.indicators[index].update(&self.candles, lua));
+                    }
+
+                    continue;
+                },
+            };
+
+            // inputs come earlier in the list, so they've already been updated for this candle
+            match self.indicators[input_index].history_as_candles(output, required) {
+                Some(candles) => try!(self.indicators[index].update(&candles, lua)),
+                None          => {}, // the input doesn't have enough history yet
+            }
+        }
+
+        Ok(())
     }
 
     pub fn has_full_data(&mut self) -> bool {
         self.candles.len() as i32 == self.max_candles
     }
 
+    // True once a candle has completed and every indicator that can be calculated has warmed
+    // up, which usually happens long before the chart fills up.  Unbound indicators are waiting
+    // for the optimizer and Lua indicators for a simulation, so they don't count yet.
+    pub fn is_ready(&self) -> bool {
+        if self.candles.len() < 2 {
+            return false;
+        }
+
+        self.indicators.iter().all( |indicator| {
+            !indicator.is_bound() || (indicator.needs_lua() && !self.active) || indicator.is_ready()
+        })
+    }
+
     fn update_latest_candle(&mut self, tick: &Tick) {
-        let mut candle = self.candles[0];
+        // Candle is Copy, so take a reference or the changes are made to a temporary
+        let candle = &mut self.candles[0];
 
         if tick.bid > candle.high_bid {
             candle.high_bid = tick.bid;
@@ -161,7 +586,12 @@ impl Chart {
         candle.volume += 1;
     }
 
-    pub fn process_tick(&mut self, tick: &Tick) {
+    // Returns true when the tick completed a candle.  `lua` is the interpreter of the simulation
+    // the chart belongs to, if any, which the candles and indicators are published to.  Errors
+    // come from Lua indicators.
+    pub fn process_tick(&mut self, tick: &Tick, lua: Option<&Lua>) -> Result<bool, LuaError> {
+        let mut completed = false;
+
         match self.chart_type {
             ChartType::Candlestick => {
                 let num_candles = self.candles.len() as i32;
@@ -169,13 +599,14 @@ impl Chart {
                 let id: i32 = tick.time.to_timespec().sec as i32 / self.seconds_per_period;
 
                 if 0 == num_candles {
-                    self.create_new_candle_from_tick(id, tick);
+                    try!(self.create_new_candle_from_tick(id, tick, lua));
                 } else {
                     if id > self.candles[0].id {
                         self.candles[0].close_bid = self.last_tick.bid;
                         self.candles[0].close_ask = self.last_tick.ask;
 
-                        self.create_new_candle_from_tick(id, tick);
+                        try!(self.create_new_candle_from_tick(id, tick, lua));
+                        completed = true;
                     } else {
                         self.update_latest_candle(tick);
                     }
@@ -184,55 +615,123 @@ impl Chart {
             _ => panic!("Unknown chart type: {}", self.chart_type),
         };
 
+        for indicator in self.indicators.iter_mut() {
+            indicator.update_on_tick(tick, lua);
+        }
+
         self.last_tick = tick.clone();
 
         self.ticks_processed += 1;
+
+        Ok(completed)
     }
 
-    fn send_to_lua(&self) {
-        if !self.active {
-            return
+    // when the candle closed, i.e. when the next one opens
+    fn close_time(&self, candle: &Candle) -> i64 {
+        (candle.id as i64 + 1) * self.seconds_per_period as i64
+    }
+
+    // close times of the last `count` completed candles, newest first
+    pub fn close_times(&self, count: uint) -> Option<Vec<i64>> {
+        if self.candles.len() < count + 1 {
+            return None;
         }
 
+        Some(self.candles.slice(1, count + 1).iter().map( |c| self.close_time(c) ).collect())
+    }
+
+    // The close bid as of each of `times` (newest first), i.e. that of the newest completed
+    // candle which had closed by then.  None when the history doesn't go back far enough.
+    pub fn closes_at(&self, times: &Vec<i64>) -> Option<Vec<f32>> {
+        let mut closes: Vec<f32> = Vec::with_capacity(times.len());
+        let mut index = 1u;
+
+        for &time in times.iter() {
+            while index < self.candles.len() && self.close_time(&self.candles[index]) > time {
+                index += 1;
+            }
+
+            if index >= self.candles.len() {
+                return None;
+            }
+
+            closes.push(self.candles[index].close_bid);
+        }
+
+        Some(closes)
+    }
+
+    pub fn get_period_seconds(&self) -> i32 {
+        self.seconds_per_period
+    }
+
+    // the most completed candles the chart holds
+    pub fn get_max_candles(&self) -> i32 {
+        self.max_candles - 1
+    }
+
+    // Each candle has its open, high, low and close bid and ask and its volume.  Lua indicators
+    // get the same table.
+    fn send_to_lua(&self, lua: &Lua) {
         let mut index = 0i32; // see comments at top of file about indexes
 
-        lua::create_table(self.candles.len() as i32);
+        lua.create_table(self.candles.len() as i32);
 
         for candle in self.candles.iter() {
-            lua::push_table_integer(index);
-            lua::create_table(5);
+            lua.push_table_integer(index);
+            lua.create_table(9);
+
+            lua.push_table_string("open_bid");
+            lua.push_table_number(candle.open_bid);
+            lua.set_table(-3);
 
-            lua::push_table_string("open_bid");
-            lua::push_table_number(candle.open_bid);
-            lua::set_table(-3);
+            lua.push_table_string("open_ask");
+            lua.push_table_number(candle.open_ask);
+            lua.set_table(-3);
 
-            lua::push_table_string("open_ask");
-            lua::push_table_number(candle.open_ask);
-            lua::set_table(-3);
+            lua.push_table_string("high_bid");
+            lua.push_table_number(candle.high_bid);
+            lua.set_table(-3);
 
-            lua::push_table_string("close_bid");
-            lua::push_table_number(candle.close_bid);
-            lua::set_table(-3);
+            lua.push_table_string("high_ask");
+            lua.push_table_number(candle.high_ask);
+            lua.set_table(-3);
 
-            lua::push_table_string("close_ask");
-            lua::push_table_number(candle.close_ask);
-            lua::set_table(-3);
+            lua.push_table_string("low_bid");
+            lua.push_table_number(candle.low_bid);
+            lua.set_table(-3);
 
-            lua::push_table_string("volume");
-            lua::push_table_integer(candle.volume);
-            lua::set_table(-3);
+            lua.push_table_string("low_ask");
+            lua.push_table_number(candle.low_ask);
+            lua.set_table(-3);
 
-            lua::set_table(-3);
+            lua.push_table_string("close_bid");
+            lua.push_table_number(candle.close_bid);
+            lua.set_table(-3);
+
+            lua.push_table_string("close_ask");
+            lua.push_table_number(candle.close_ask);
+            lua.set_table(-3);
+
+            lua.push_table_string("volume");
+            lua.push_table_integer(candle.volume);
+            lua.set_table(-3);
+
+            lua.set_table(-3);
             index += 1;
         }
 
-        lua::finalize_table(self.name.as_slice());
+        lua.finalize_table(self.name.as_slice());
     }
 
     pub fn set_active(&mut self) {
         self.active = true;
     }
 
+    pub fn get_name(&self) -> &str {
+        self.name.as_slice()
+    }
+
     pub fn get_ticks_processed(&self) -> i32 {
         self.ticks_processed
     }
@@ -1,14 +1,126 @@
+extern crate time;
+
 use std::mem;
 
-use lua;
+use chart::PriceSource;
+use config_tables;
+use lua::{Lua, Sandbox};
+use parsers;
 
 static mut CONFIG: *mut ConfigurationFile = 0 as *mut ConfigurationFile;
 
+// ----- ERRORS ------------------------------------------------------------------------------------
+
+// A mistake in the config.  `path` is where it is, e.g. "charts[2].indicators[1]" or "STEPS".
+#[derive(Clone,Show)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(path: &str, message: String) -> ConfigError {
+        ConfigError {
+            path: path.to_string(),
+            message: message,
+        }
+    }
+}
+
+// For settings that were checked when the config was loaded, or that don't come from it.
+pub fn expect_valid<T>(result: Result<T, ConfigError>) -> T {
+    match result {
+        Ok(value)  => value,
+        Err(error) => panic!("config error at {}: {}", error.path, error.message),
+    }
+}
+
+// ----- SETTINGS ----------------------------------------------------------------------------------
+
+// What the charts, variables and cross indicators are built from (see parsers::build_charts()
+// and friends).  They're read from the config's charts, variables and cross_indicators tables
+// (see config_tables.rs), or parsed from the older CHARTS, VARIABLES and CROSS_INDICATORS
+// strings.  `path` is where each one came from for error messages, e.g. "charts[2]".
+
+#[derive(Clone,Show)]
+pub struct ChartSettings {
+    pub path: String,
+    pub chart_type: String,
+    pub period: String,
+    pub candles: i32,
+    pub indicators: Vec<IndicatorSettings>,
+}
+
+// e.g. {"sma", 20, "hlc3_mid", history=5} or "sma,20,hlc3_mid,history=5"
+#[derive(Clone,Show)]
+pub struct IndicatorSettings {
+    pub path: String,
+    pub indicator_type: String,
+
+    // what a "lua" indicator calls, e.g. "my_indicator" in "lua,my_indicator"
+    pub function: Option<String>,
+
+    pub params: Vec<IndicatorParam>,
+
+    // name=, which replaces everything after the chart name in the Lua name
+    pub name: Option<String>,
+
+    // history=, 0 when none is kept
+    pub history: i32,
+
+    // the indicator's own, e.g. doji_body=0.05
+    pub options: Vec<(String, f32)>,
+}
+
+// see parsers::parse_charts()
+#[derive(Clone,Show)]
+pub enum IndicatorParam {
+    // with how it was written, which goes into the Lua name
+    Number(f32, String),
+
+    // "$name"
+    Variable(String),
+
+    Source(PriceSource, String),
+
+    // another indicator on the same chart, without the chart prefix, e.g. "rsi_14"
+    Input(String),
+}
+
+#[derive(Clone,Copy,Show)]
+pub enum VariableKind {
+    Bool,
+    Float(f32, f32), // lower and upper bound
+    Int(i32, i32),
+}
+
+#[derive(Clone,Show)]
+pub struct VariableSettings {
+    pub path: String,
+    pub name: String,
+    pub kind: VariableKind,
+}
+
+#[derive(Clone,Show)]
+pub struct CrossIndicatorSettings {
+    pub path: String,
+    pub indicator_type: String,
+    pub chart_a: String,
+    pub chart_b: String,
+    pub period: Option<i32>,
+    pub name: Option<String>,
+}
+
+// ----- CONFIGURATION FILE ------------------------------------------------------------------------
+
 pub struct ConfigurationFile {
     pub csv_path: String,
 
-    pub charts: String,
-    pub variables: String,
+    pub charts: Vec<ChartSettings>,
+    pub variables: Vec<VariableSettings>,
+
+    // indicators across two charts, see parsers::parse_cross_indicators()
+    pub cross_indicators: Vec<CrossIndicatorSettings>,
 
     pub in_sample:     String,
     pub out_of_sample: String,
@@ -19,6 +131,20 @@ pub struct ConfigurationFile {
     pub jpy_base: bool,
 
     pub post_run_script: String,
+
+    // seconds of ticks the tick statistics are calculated over
+    pub tick_window: i32,
+
+    // account leverage, e.g. 100 for 100:1
+    pub leverage: i32,
+
+    // Seeds the optimizer and every simulation's math.random().  Picked from the clock when the
+    // config doesn't set SEED, and printed either way.
+    pub seed: i32,
+
+    // Limits for the strategy's interpreters.  This file is read without them, so they only
+    // apply once the simulations start.
+    pub sandbox: Sandbox,
 }
 
 pub fn get<'a>() -> &'a mut ConfigurationFile {
@@ -28,32 +154,89 @@ pub fn get<'a>() -> &'a mut ConfigurationFile {
 }
 
 impl ConfigurationFile {
-    pub fn load_from_file(path: &str) {
-        lua::setup(path);
+    // Reads the config and checks the charts, variables and cross indicators by building them
+    // once, so a mistake is reported here rather than at the first simulation.
+    pub fn load_from_file(path: &str) -> Result<(), ConfigError> {
+        let lua = match Lua::new(path) {
+            Ok(lua)    => lua,
+            Err(error) => return Err(ConfigError::new(
+                path,
+                format!("failed to load config file: {}", error.message)
+            )),
+        };
+
+        let csv_path = lua.get_string_var("CSV_PATH");
 
-        let csv_path = lua::get_string_var("CSV_PATH");
+        let in_sample     = lua.get_string_var("IN_SAMPLE_TIME");
+        let out_of_sample = lua.get_string_var("OUT_OF_SAMPLE_TIME");
 
-        let in_sample     = lua::get_string_var("IN_SAMPLE_TIME");
-        let out_of_sample = lua::get_string_var("OUT_OF_SAMPLE_TIME");
+        let iterations = lua.get_int_var("ITERATIONS");
+        let steps      = lua.get_int_var("STEPS");
 
-        let iterations = lua::get_int_var("ITERATIONS");
-        let steps      = lua::get_int_var("STEPS");
+        // the tables are used when they're there, otherwise the strings
+
+        let charts = match try!(config_tables::read_charts(&lua)) {
+            Some(charts) => charts,
+            None         => try!(parsers::parse_chart_settings(lua.get_string_var("CHARTS"))),
+        };
 
-        let charts     = lua::get_string_var("CHARTS");
-        let variables  = lua::get_string_var("VARIABLES");
+        let variables = match try!(config_tables::read_variables(&lua)) {
+            Some(variables) => variables,
+            None            => try!(parsers::parse_variable_settings(
+                lua.get_string_var("VARIABLES")
+            )),
+        };
+
+        let cross_indicators = match try!(config_tables::read_cross_indicators(&lua)) {
+            Some(indicators) => indicators,
+            None             => try!(parsers::parse_cross_indicator_settings(
+                lua.get_optional_string_var("CROSS_INDICATORS", "")
+            )),
+        };
 
         let jpy_base = false; // is set later
 
-        let post_run_script = lua::get_string_var("POST_RUN_SCRIPT");
+        let post_run_script = lua.get_string_var("POST_RUN_SCRIPT");
 
-        lua::teardown();
+        let tick_window = lua.get_optional_int_var("TICK_WINDOW_SECONDS", 60);
+
+        let leverage = lua.get_optional_int_var("LEVERAGE", 100);
+
+        let seed = lua.get_optional_int_var("SEED", time::precise_time_ns() as i32);
+
+        let sandbox = Sandbox {
+            unsafe_libs: lua.get_optional_bool_var("LUA_UNSAFE_LIBS", false),
+            instruction_limit: lua.get_optional_int_var("LUA_INSTRUCTION_LIMIT", 100000000),
+            memory_limit: lua.get_optional_int_var("LUA_MEMORY_LIMIT_MB", 512),
+        };
+
+        drop(lua);
 
         if steps < 1 {
-            panic!("STEPS must be > 0");
+            return Err(ConfigError::new("STEPS", "must be > 0".to_string()));
+        }
+
+        if tick_window < 1 {
+            return Err(ConfigError::new("TICK_WINDOW_SECONDS", "must be > 0".to_string()));
         }
 
+        if leverage < 1 {
+            return Err(ConfigError::new("LEVERAGE", "must be > 0".to_string()));
+        }
+
+        if sandbox.instruction_limit < 0 {
+            return Err(ConfigError::new("LUA_INSTRUCTION_LIMIT", "must be >= 0".to_string()));
+        }
+
+        if sandbox.memory_limit < 0 {
+            return Err(ConfigError::new("LUA_MEMORY_LIMIT_MB", "must be >= 0".to_string()));
+        }
+
+        try!(parsers::check_settings(&charts, &variables, &cross_indicators));
+
         let config = ConfigurationFile {
             charts: charts,
+            cross_indicators: cross_indicators,
             csv_path: csv_path,
             in_sample: in_sample,
             out_of_sample: out_of_sample,
@@ -62,12 +245,18 @@ impl ConfigurationFile {
             steps: steps,
             jpy_base: jpy_base,
             post_run_script: post_run_script,
+            tick_window: tick_window,
+            leverage: leverage,
+            seed: seed,
+            sandbox: sandbox,
         };
 
         unsafe {
             let box_config = Box::new(config);
             CONFIG = mem::transmute(box_config);
         }
+
+        Ok(())
     }
 
     pub fn set_jpy_base(&mut self) {
@@ -0,0 +1,460 @@
+// Reads the config's charts, variables and cross_indicators tables into settings.  Each reader
+// returns None when its table isn't there, so the older strings can be used instead, and an
+// error naming the field that's wrong, e.g. "charts[1].indicators[2].history".
+//
+// charts = {
+//     {
+//         type = "candlestick", period = "M15", candles = 60,
+//         indicators = {
+//             {"ema", 20},
+//             {"sma", "$sma_period", "hlc3_mid", name = "slow_sma", history = 5},
+//         },
+//     },
+// }
+//
+// variables = {
+//     sma_period = {"int", 5, 50},
+//     threshold  = {"float", 0.1, 0.9},
+//     use_filter = "bool",
+// }
+//
+// cross_indicators = {
+//     {"correlation", "candlestick_M5", "candlestick_M15", 50},
+//     {"ratio", "candlestick_M5", "candlestick_H1", name = "m5_h1"},
+// }
+
+use chart;
+use config::{ChartSettings, ConfigError, CrossIndicatorSettings, IndicatorParam};
+use config::{IndicatorSettings, VariableKind, VariableSettings};
+use cross_indicators::CrossIndicatorType;
+use indicators::registry;
+use lua::{Lua, LuaType};
+use parsers;
+
+fn fail<T>(path: &str, message: &str) -> Result<T, ConfigError> {
+    Err(ConfigError::new(path, message.to_string()))
+}
+
+fn expect(path: &str, actual: LuaType, expected: LuaType) -> Result<(), ConfigError> {
+    match actual == expected {
+        true  => Ok(()),
+        false => fail(
+            path,
+            format!("expected {}, got {}", expected.name(), actual.name()).as_slice()
+        ),
+    }
+}
+
+fn check_keys(lua: &Lua, path: &str, allowed: &[&str]) -> Result<(), ConfigError> {
+    for key in lua.string_keys().iter() {
+        if !allowed.iter().any( |&name| name == key.as_slice() ) {
+            return fail(path, format!("unknown field: {}", key).as_slice());
+        }
+    }
+
+    Ok(())
+}
+
+fn read_string_field(lua: &Lua, path: &str, name: &str) -> Result<String, ConfigError> {
+    let field_path = format!("{}.{}", path, name);
+
+    let t = lua.push_field(name);
+    try!(expect(field_path.as_slice(), t, LuaType::String));
+
+    let value = lua.string();
+    lua.pop();
+
+    Ok(value)
+}
+
+fn read_integer(lua: &Lua, path: &str) -> Result<i32, ConfigError> {
+    let value = lua.number();
+
+    if value != value.floor() {
+        return fail(path, format!("expected a whole number, got {}", value).as_slice());
+    }
+
+    Ok(value as i32)
+}
+
+// ===== CHARTS ====================================================================================
+
+// a number parameter as it'd be written in the string form, for the Lua name
+fn number_text(value: f64) -> String {
+    match value == value.floor() {
+        true  => format!("{}", value as i64),
+        false => format!("{}", value),
+    }
+}
+
+// {"type", param, ..., option = value, ...} on top of the stack
+fn read_indicator(lua: &Lua, path: String) -> Result<IndicatorSettings, ConfigError> {
+    let length = lua.length();
+
+    if length < 1 {
+        return fail(path.as_slice(), "indicator needs a type, e.g. {\"ema\", 20}");
+    }
+
+    let t = lua.push_index(1);
+    let type_path = format!("{}[1]", path);
+    try!(expect(type_path.as_slice(), t, LuaType::String));
+    let indicator_type = lua.string();
+    lua.pop();
+
+    // Lua indicators take name= and history= like the others, but no options of their own
+    let mut allowed = vec!("name", "history");
+
+    if "lua" != indicator_type.as_slice() {
+        match registry::get().lookup(indicator_type.as_slice()) {
+            Some(registered) => allowed.push_all(registered.options.as_slice()),
+            None             => return fail(
+                type_path.as_slice(),
+                format!("unknown indicator type: {}", indicator_type).as_slice()
+            ),
+        }
+    }
+
+    try!(check_keys(lua, path.as_slice(), allowed.as_slice()));
+
+    let mut function: Option<String> = None;
+    let mut params: Vec<IndicatorParam> = vec!();
+
+    for i in range(2, length + 1) {
+        let param_path = format!("{}[{}]", path, i);
+        let param_path = param_path.as_slice();
+
+        let param = match lua.push_index(i) {
+            LuaType::Number => {
+                let value = lua.number();
+                IndicatorParam::Number(value as f32, number_text(value))
+            },
+            LuaType::String if "lua" == indicator_type.as_slice() && function.is_none() => {
+                function = Some(lua.string());
+                lua.pop();
+                continue;
+            },
+            LuaType::String => {
+                let text = lua.string();
+                try!(parsers::parse_indicator_param(param_path, text.as_slice()))
+            },
+            t               => return fail(
+                param_path,
+                format!("expected a number or a string, got {}", t.name()).as_slice()
+            ),
+        };
+        lua.pop();
+
+        params.push(param);
+    }
+
+    let name = match lua.push_field("name") {
+        LuaType::Nil => None,
+        t            => {
+            let name_path = format!("{}.name", path);
+            try!(expect(name_path.as_slice(), t, LuaType::String));
+            Some(lua.string())
+        },
+    };
+    lua.pop();
+
+    let history_path = format!("{}.history", path);
+
+    let history = match lua.push_field("history") {
+        LuaType::Nil => 0,
+        t            => {
+            try!(expect(history_path.as_slice(), t, LuaType::Number));
+            let history = try!(read_integer(lua, history_path.as_slice()));
+
+            if history < 1 {
+                return fail(history_path.as_slice(), "must be > 0");
+            }
+
+            history
+        },
+    };
+    lua.pop();
+
+    let mut options: Vec<(String, f32)> = vec!();
+
+    for key in lua.string_keys().iter() {
+        if "name" == key.as_slice() || "history" == key.as_slice() {
+            continue;
+        }
+
+        let option_path = format!("{}.{}", path, key);
+
+        let t = lua.push_field(key.as_slice());
+        try!(expect(option_path.as_slice(), t, LuaType::Number));
+        options.push((key.clone(), lua.number() as f32));
+        lua.pop();
+    }
+
+    Ok(IndicatorSettings {
+        path: path,
+        indicator_type: indicator_type,
+        function: function,
+        params: params,
+        name: name,
+        history: history,
+        options: options,
+    })
+}
+
+// one of the charts on top of the stack
+fn read_chart(lua: &Lua, path: String) -> Result<ChartSettings, ConfigError> {
+    try!(check_keys(lua, path.as_slice(), &["type", "period", "candles", "indicators"]));
+
+    let chart_type = try!(read_string_field(lua, path.as_slice(), "type"));
+    let period = try!(read_string_field(lua, path.as_slice(), "period"));
+
+    if chart::period_from_string(period.as_slice()).is_none() {
+        let period_path = format!("{}.period", path);
+        return fail(period_path.as_slice(), format!("unknown period: {}", period).as_slice());
+    }
+
+    let candles_path = format!("{}.candles", path);
+    let t = lua.push_field("candles");
+    try!(expect(candles_path.as_slice(), t, LuaType::Number));
+    let candles = try!(read_integer(lua, candles_path.as_slice()));
+    lua.pop();
+
+    if candles < 1 {
+        return fail(candles_path.as_slice(), "must be > 0");
+    }
+
+    let mut indicators: Vec<IndicatorSettings> = vec!();
+
+    let indicators_path = format!("{}.indicators", path);
+
+    match lua.push_field("indicators") {
+        LuaType::Nil   => {},
+        LuaType::Table => {
+            for i in range(1, lua.length() + 1) {
+                let indicator_path = format!("{}[{}]", indicators_path, i);
+
+                let t = lua.push_index(i);
+                try!(expect(indicator_path.as_slice(), t, LuaType::Table));
+                indicators.push(try!(read_indicator(lua, indicator_path)));
+                lua.pop();
+            }
+        },
+        t              => try!(expect(indicators_path.as_slice(), t, LuaType::Table)),
+    }
+
+    lua.pop();
+
+    Ok(ChartSettings {
+        path: path,
+        chart_type: chart_type,
+        period: period,
+        candles: candles,
+        indicators: indicators,
+    })
+}
+
+pub fn read_charts(lua: &Lua) -> Result<Option<Vec<ChartSettings>>, ConfigError> {
+    let t = lua.push_global("charts");
+
+    if LuaType::Nil == t {
+        lua.pop();
+        return Ok(None);
+    }
+
+    try!(expect("charts", t, LuaType::Table));
+
+    let mut charts: Vec<ChartSettings> = vec!();
+
+    for i in range(1, lua.length() + 1) {
+        let path = format!("charts[{}]", i);
+
+        let t = lua.push_index(i);
+        try!(expect(path.as_slice(), t, LuaType::Table));
+        charts.push(try!(read_chart(lua, path)));
+        lua.pop();
+    }
+
+    lua.pop();
+
+    if 0 == charts.len() {
+        return fail("charts", "needs at least one chart");
+    }
+
+    Ok(Some(charts))
+}
+
+// ===== VARIABLES =================================================================================
+
+fn read_bound(lua: &Lua, path: &str, index: i32) -> Result<f64, ConfigError> {
+    let bound_path = format!("{}[{}]", path, index);
+
+    let t = lua.push_index(index);
+    try!(expect(bound_path.as_slice(), t, LuaType::Number));
+    let value = lua.number();
+    lua.pop();
+
+    Ok(value)
+}
+
+// "bool", {"bool"}, {"int", lower, upper} or {"float", lower, upper} on top of the stack
+fn read_variable_kind(lua: &Lua, path: &str, t: LuaType) -> Result<VariableKind, ConfigError> {
+    let var_type = match t {
+        LuaType::String => lua.string(),
+        LuaType::Table  => {
+            let type_path = format!("{}[1]", path);
+
+            let t = lua.push_index(1);
+            try!(expect(type_path.as_slice(), t, LuaType::String));
+            let var_type = lua.string();
+            lua.pop();
+
+            var_type
+        },
+        _               => return fail(
+            path,
+            format!("expected a string or a table, got {}", t.name()).as_slice()
+        ),
+    };
+
+    let (lower, upper) = match var_type.as_slice() {
+        "bool"            => return Ok(VariableKind::Bool),
+        "float" | "int"   => {
+            try!(expect(path, t, LuaType::Table));
+            (try!(read_bound(lua, path, 2)), try!(read_bound(lua, path, 3)))
+        },
+        other             => return fail(
+            path,
+            format!("unknown variable type: {}", other).as_slice()
+        ),
+    };
+
+    if lower >= upper {
+        return fail(path, "lower bound must be < upper bound");
+    }
+
+    match var_type.as_slice() {
+        "float" => Ok(VariableKind::Float(lower as f32, upper as f32)),
+        _       => {
+            if lower != lower.floor() || upper != upper.floor() {
+                return fail(path, "int bounds must be whole numbers");
+            }
+
+            Ok(VariableKind::Int(lower as i32, upper as i32))
+        },
+    }
+}
+
+pub fn read_variables(lua: &Lua) -> Result<Option<Vec<VariableSettings>>, ConfigError> {
+    let t = lua.push_global("variables");
+
+    if LuaType::Nil == t {
+        lua.pop();
+        return Ok(None);
+    }
+
+    try!(expect("variables", t, LuaType::Table));
+
+    // string_keys() skips the rest, which would leave {{"fast", "int", 5, 20}} with no variables
+    if lua.length() > 0 {
+        return fail("variables[1]", "must be named, e.g. variables = {fast = {\"int\", 5, 20}}");
+    }
+
+    let mut variables: Vec<VariableSettings> = vec!();
+
+    for name in lua.string_keys().iter() {
+        let path = format!("variables.{}", name);
+
+        let t = lua.push_field(name.as_slice());
+        let kind = try!(read_variable_kind(lua, path.as_slice(), t));
+        lua.pop();
+
+        variables.push(VariableSettings { path: path, name: name.clone(), kind: kind });
+    }
+
+    lua.pop();
+
+    Ok(Some(variables))
+}
+
+// ===== CROSS INDICATORS ==========================================================================
+
+// {"type", "chart_a", "chart_b", period, name = "alias"} on top of the stack
+fn read_cross_indicator(lua: &Lua, path: String) -> Result<CrossIndicatorSettings, ConfigError> {
+    try!(check_keys(lua, path.as_slice(), &["name"]));
+
+    let mut parts: Vec<String> = vec!();
+
+    for i in range(1, 4) {
+        let part_path = format!("{}[{}]", path, i);
+
+        let t = lua.push_index(i);
+        try!(expect(part_path.as_slice(), t, LuaType::String));
+        parts.push(lua.string());
+        lua.pop();
+    }
+
+    if CrossIndicatorType::from_string(parts[0].as_slice()).is_none() {
+        let type_path = format!("{}[1]", path);
+        return fail(
+            type_path.as_slice(),
+            format!("unknown cross indicator type: {}", parts[0]).as_slice()
+        );
+    }
+
+    let period_path = format!("{}[4]", path);
+
+    let period = match lua.push_index(4) {
+        LuaType::Nil    => None,
+        LuaType::Number => Some(try!(read_integer(lua, period_path.as_slice()))),
+        t               => {
+            try!(expect(period_path.as_slice(), t, LuaType::Number));
+            None
+        },
+    };
+    lua.pop();
+
+    let name = match lua.push_field("name") {
+        LuaType::Nil => None,
+        t            => {
+            let name_path = format!("{}.name", path);
+            try!(expect(name_path.as_slice(), t, LuaType::String));
+            Some(lua.string())
+        },
+    };
+    lua.pop();
+
+    Ok(CrossIndicatorSettings {
+        path: path,
+        indicator_type: parts[0].clone(),
+        chart_a: parts[1].clone(),
+        chart_b: parts[2].clone(),
+        period: period,
+        name: name,
+    })
+}
+
+pub fn read_cross_indicators(lua: &Lua)
+                             -> Result<Option<Vec<CrossIndicatorSettings>>, ConfigError> {
+    let t = lua.push_global("cross_indicators");
+
+    if LuaType::Nil == t {
+        lua.pop();
+        return Ok(None);
+    }
+
+    try!(expect("cross_indicators", t, LuaType::Table));
+
+    let mut indicators: Vec<CrossIndicatorSettings> = vec!();
+
+    for i in range(1, lua.length() + 1) {
+        let path = format!("cross_indicators[{}]", i);
+
+        let t = lua.push_index(i);
+        try!(expect(path.as_slice(), t, LuaType::Table));
+        indicators.push(try!(read_cross_indicator(lua, path)));
+        lua.pop();
+    }
+
+    lua.pop();
+
+    Ok(Some(indicators))
+}
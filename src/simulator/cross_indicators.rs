@@ -0,0 +1,233 @@
+// Indicators calculated across two charts, e.g. for pairs trading or hedging.  The charts'
+// completed candles are lined up by when they closed: the chart with the longer period sets the
+// times and the other one is sampled at them, so M5 against H1 compares hourly closes.  Charts
+// only differ by period while a single CSV file is loaded, so until then these compare one
+// instrument on two timeframes.
+
+use std::cmp;
+use std::num::Float;
+
+use chart::Chart;
+use lua::Lua;
+
+#[derive(Clone,Copy,Show)]
+pub enum CrossIndicatorType {
+    Correlation,  // Pearson correlation of the close-to-close log returns
+    Ratio,        // latest close of A / latest close of B
+    SpreadZScore, // z-score of the latest log(A) - log(B) against the period's
+    Beta,         // A's returns regressed on B's
+}
+
+impl CrossIndicatorType {
+    pub fn from_string(s: &str) -> Option<CrossIndicatorType> {
+        match s {
+            "correlation" => Some(CrossIndicatorType::Correlation),
+            "ratio"       => Some(CrossIndicatorType::Ratio),
+            "zscore"      => Some(CrossIndicatorType::SpreadZScore),
+            "beta"        => Some(CrossIndicatorType::Beta),
+            _             => None,
+        }
+    }
+
+    // completed candles each chart needs for a value
+    fn closes_required(&self, period: uint) -> uint {
+        match *self {
+            CrossIndicatorType::Correlation  => period + 1,
+            CrossIndicatorType::Ratio        => 1,
+            CrossIndicatorType::SpreadZScore => period,
+            CrossIndicatorType::Beta         => period + 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CrossIndicator {
+    name: String,
+    indicator_type: CrossIndicatorType,
+
+    // Lua names of the charts
+    chart_a: String,
+    chart_b: String,
+
+    // positions of the charts in the simulation, see set_chart_indexes()
+    chart_indexes: (uint, uint),
+
+    period: uint,
+    value: f32,
+
+    // set once both charts had enough candles for a value
+    ready: bool,
+}
+
+// ===== GLOBAL FUNCTIONS ==========================================================================
+
+fn mean(values: &Vec<f32>) -> f32 {
+    values.iter().fold(0.0f32, |sum, &v| sum + v) / values.len() as f32
+}
+
+// population covariance
+fn covariance(a: &Vec<f32>, b: &Vec<f32>) -> f32 {
+    let mean_a = mean(a);
+    let mean_b = mean(b);
+
+    let mut sum = 0.0f32;
+
+    for (&x, &y) in a.iter().zip(b.iter()) {
+        sum += (x - mean_a) * (y - mean_b);
+    }
+
+    sum / a.len() as f32
+}
+
+// newest first, like the closes
+fn log_returns(closes: &Vec<f32>) -> Vec<f32> {
+    range(0, closes.len() - 1).map( |i| (closes[i] / closes[i + 1]).ln() ).collect()
+}
+
+// ===== CROSS INDICATOR ===========================================================================
+
+impl CrossIndicator {
+    pub fn new(name: &str,
+               it: CrossIndicatorType,
+               chart_a: &str,
+               chart_b: &str,
+               period: uint) -> CrossIndicator {
+        match it {
+            CrossIndicatorType::Ratio => {},
+            _                         => {
+                if period < 2 {
+                    panic!("{}: period must be > 1, got {}", name, period);
+                }
+            },
+        }
+
+        CrossIndicator {
+            name: name.to_string(),
+            indicator_type: it,
+            chart_a: chart_a.to_string(),
+            chart_b: chart_b.to_string(),
+            chart_indexes: (0, 0),
+            period: period,
+            value: 0.0,
+            ready: false,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_slice()
+    }
+
+    pub fn get_chart_names(&self) -> (&str, &str) {
+        (self.chart_a.as_slice(), self.chart_b.as_slice())
+    }
+
+    pub fn set_chart_indexes(&mut self, a: uint, b: uint) {
+        self.chart_indexes = (a, b);
+    }
+
+    pub fn get_chart_indexes(&self) -> (uint, uint) {
+        self.chart_indexes
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    // Both charts have to hold as much time as the closes needed from the one with the longer
+    // period, or the indicator never gets a value.
+    pub fn check_history(&self, a: &Chart, b: &Chart) -> Result<(), String> {
+        let count  = self.indicator_type.closes_required(self.period) as i64;
+        let longer = cmp::max(a.get_period_seconds(), b.get_period_seconds()) as i64;
+
+        for chart in [a, b].iter() {
+            let seconds  = chart.get_period_seconds() as i64;
+            let required = (count * longer + seconds - 1) / seconds;
+
+            if (chart.get_max_candles() as i64) < required {
+                return Err(format!(
+                    "{}: chart {} must hold at least {} candles to line up with the other chart, \
+                     it holds {}",
+                    self.name,
+                    chart.get_name(),
+                    required,
+                    chart.get_max_candles()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Called when either chart completes a candle.  Nothing changes until both charts have
+    // enough candles.
+    pub fn update(&mut self, a: &Chart, b: &Chart) {
+        let count = self.indicator_type.closes_required(self.period);
+
+        let longer = match a.get_period_seconds() >= b.get_period_seconds() {
+            true  => a,
+            false => b,
+        };
+
+        let times = match longer.close_times(count) {
+            Some(times) => times,
+            None        => return,
+        };
+
+        let (closes_a, closes_b) = match (a.closes_at(&times), b.closes_at(&times)) {
+            (Some(closes_a), Some(closes_b)) => (closes_a, closes_b),
+            _                                => return,
+        };
+
+        self.ready = true;
+
+        self.value = match self.indicator_type {
+            CrossIndicatorType::Ratio => {
+                match 0.0 == closes_b[0] {
+                    true  => 0.0,
+                    false => closes_a[0] / closes_b[0],
+                }
+            },
+            CrossIndicatorType::Correlation => {
+                let returns_a = log_returns(&closes_a);
+                let returns_b = log_returns(&closes_b);
+
+                let deviations = covariance(&returns_a, &returns_a).sqrt() *
+                                 covariance(&returns_b, &returns_b).sqrt();
+
+                match 0.0 == deviations {
+                    true  => 0.0,
+                    false => covariance(&returns_a, &returns_b) / deviations,
+                }
+            },
+            CrossIndicatorType::Beta => {
+                let returns_a = log_returns(&closes_a);
+                let returns_b = log_returns(&closes_b);
+
+                let variance = covariance(&returns_b, &returns_b);
+
+                match 0.0 == variance {
+                    true  => 0.0,
+                    false => covariance(&returns_a, &returns_b) / variance,
+                }
+            },
+            CrossIndicatorType::SpreadZScore => {
+                let spreads: Vec<f32> = closes_a.iter()
+                                                .zip(closes_b.iter())
+                                                .map( |(&x, &y)| x.ln() - y.ln() )
+                                                .collect();
+
+                let deviation = covariance(&spreads, &spreads).sqrt();
+
+                match 0.0 == deviation {
+                    true  => 0.0,
+                    false => (spreads[0] - mean(&spreads)) / deviation,
+                }
+            },
+        };
+    }
+
+    pub fn send_to_lua(&self, lua: &Lua) {
+        lua.register_number(self.name.as_slice(), self.value);
+        lua.register_boolean(format!("{}_ready", self.name).as_slice(), self.ready);
+    }
+}
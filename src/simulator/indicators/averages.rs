@@ -0,0 +1,96 @@
+use chart::{Candle, PriceSource};
+use indicators::{Indicator, IndicatorDefinition};
+use indicators::indicator_utils;
+use indicators::indicator_utils::Smoother;
+
+// ===== SMA =======================================================================================
+
+#[derive(Clone)]
+pub struct SimpleMovingAverage {
+    period: uint,
+    source: PriceSource,
+    value: f32,
+}
+
+impl SimpleMovingAverage {
+    pub fn new(period: uint, source: PriceSource) -> SimpleMovingAverage {
+        SimpleMovingAverage { period: period, source: source, value: 0.0 }
+    }
+}
+
+impl Indicator for SimpleMovingAverage {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        let mut avg = 0.0f32;
+
+        // start at 1 to skip the first incomplete candle
+        for i in range(1, self.period + 1) {
+            avg += self.source.value(&candles[i]);
+        }
+
+        self.value = avg / self.period as f32;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.value)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_sma(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(SimpleMovingAverage::new(definition.period(0), definition.source)) as Box<Indicator>
+}
+
+// ===== EMA =======================================================================================
+
+#[derive(Clone)]
+pub struct ExponentialMovingAverage {
+    period: uint,
+    source: PriceSource,
+    primed: bool,
+    average: Smoother,
+}
+
+impl ExponentialMovingAverage {
+    pub fn new(period: uint, source: PriceSource) -> ExponentialMovingAverage {
+        ExponentialMovingAverage {
+            period: period,
+            source: source,
+            primed: false,
+            average: Smoother::ema(period),
+        }
+    }
+}
+
+impl Indicator for ExponentialMovingAverage {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            self.average.push(self.source.value(&candles[i]));
+        }
+
+        self.primed = true;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.average.value)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_ema(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let ema = ExponentialMovingAverage::new(definition.period(0), definition.source);
+    Box::new(ema) as Box<Indicator>
+}
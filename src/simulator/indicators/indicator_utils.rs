@@ -0,0 +1,190 @@
+use std::num::Float;
+
+use chart::{Candle, PriceSide, PriceSource};
+
+#[cfg(test)]
+use indicators::Indicator;
+
+// Exponential smoothing seeded with the simple average of the first `period` values.  Wilder's
+// smoothing is the same thing with alpha = 1 / period.
+#[derive(Clone)]
+pub struct Smoother {
+    period: uint,
+    alpha: f32,
+    count: uint,
+    pub value: f32,
+}
+
+impl Smoother {
+    pub fn ema(period: uint) -> Smoother {
+        Smoother { period: period, alpha: 2.0 / (period as f32 + 1.0), count: 0, value: 0.0 }
+    }
+
+    pub fn wilder(period: uint) -> Smoother {
+        Smoother { period: period, alpha: 1.0 / period as f32, count: 0, value: 0.0 }
+    }
+
+    pub fn push(&mut self, x: f32) -> f32 {
+        self.count += 1;
+
+        if self.count <= self.period {
+            self.value += (x - self.value) / self.count as f32;
+        } else {
+            self.value += self.alpha * (x - self.value);
+        }
+
+        self.value
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.count >= self.period
+    }
+}
+
+// Stateful indicators replay the whole chart history on their first update and after that are
+// only fed the candle that just completed.  Returns the candle indexes to feed, oldest first.
+pub fn new_candle_indexes(primed: bool, candles: &Vec<Candle>) -> Vec<uint> {
+    let oldest = match primed {
+        true  => 1,
+        false => candles.len() - 1,
+    };
+
+    range(1, oldest + 1).rev().collect()
+}
+
+// candles[0] is the incomplete candle, so offsets start at 1
+pub fn highest_high(candles: &Vec<Candle>, side: PriceSide, offset: uint, count: uint) -> f32 {
+    let mut high = candles[offset].high(side);
+
+    for i in range(offset + 1, offset + count) {
+        if candles[i].high(side) > high {
+            high = candles[i].high(side);
+        }
+    }
+
+    high
+}
+
+pub fn lowest_low(candles: &Vec<Candle>, side: PriceSide, offset: uint, count: uint) -> f32 {
+    let mut low = candles[offset].low(side);
+
+    for i in range(offset + 1, offset + count) {
+        if candles[i].low(side) < low {
+            low = candles[i].low(side);
+        }
+    }
+
+    low
+}
+
+pub fn mean_price(candles: &Vec<Candle>, source: PriceSource, offset: uint, count: uint) -> f32 {
+    let mut sum = 0.0f32;
+
+    for i in range(offset, offset + count) {
+        sum += source.value(&candles[i]);
+    }
+
+    sum / count as f32
+}
+
+pub fn typical_price(candle: &Candle, side: PriceSide) -> f32 {
+    (candle.high(side) + candle.low(side) + candle.close(side)) / 3.0
+}
+
+// the oldest candle on the chart has no previous close, so fall back to its own range
+pub fn true_range(candles: &Vec<Candle>, side: PriceSide, index: uint) -> f32 {
+    let candle = &candles[index];
+
+    if index + 1 >= candles.len() {
+        return candle.high(side) - candle.low(side);
+    }
+
+    let previous_close = candles[index + 1].close(side);
+
+    (candle.high(side) - candle.low(side))
+        .max((candle.high(side) - previous_close).abs())
+        .max((candle.low(side) - previous_close).abs())
+}
+
+// ===== TEST HELPERS ==============================================================================
+
+// Feeds `bars` (open, high, low, close, oldest first) to `indicator` one completed candle at a
+// time, the way a chart does.  Returns the outputs after each bar, or None before the indicator
+// has warmed up.
+#[cfg(test)]
+pub fn run_over_bars(indicator: &mut Indicator,
+                     bars: &[(f32, f32, f32, f32)]) -> Vec<Option<Vec<f32>>> {
+    let mut results: Vec<Option<Vec<f32>>> = vec!();
+    let mut candles: Vec<Candle> = vec!();
+
+    for &(open, high, low, close) in bars.iter() {
+        candles.insert(0, Candle::from_prices(open, high, low, close));
+
+        if candles.len() < indicator.warmup() {
+            results.push(None);
+            continue;
+        }
+
+        // the incomplete candle the chart keeps at index 0
+        let mut chart = vec!(Candle::from_value(close));
+        chart.push_all(candles.as_slice());
+
+        indicator.update(&chart);
+        results.push(Some(indicator.outputs()));
+    }
+
+    results
+}
+
+#[cfg(test)]
+pub fn assert_close(actual: f32, expected: f32, tolerance: f32, what: &str) {
+    if (actual - expected).abs() > tolerance {
+        panic!("{}: expected {} (+/- {}), got {}", what, expected, tolerance, actual);
+    }
+}
+
+// Forty daily bars: a rally, a sell-off and a recovery, so the directional movement goes both
+// ways and the parabolic SAR reverses twice
+#[cfg(test)]
+pub static REFERENCE_BARS: [(f32, f32, f32, f32); 40] = [
+    (50.00, 51.04, 49.62, 50.60),
+    (50.60, 51.36, 50.29, 51.00),
+    (51.00, 52.32, 50.55, 51.80),
+    (51.80, 52.20, 51.15, 51.50),
+    (51.50, 52.68, 51.08, 52.20),
+    (52.20, 53.02, 51.92, 52.70),
+    (52.70, 54.16, 52.21, 53.60),
+    (53.60, 54.00, 53.05, 53.40),
+    (53.40, 54.36, 53.09, 54.00),
+    (54.00, 54.84, 53.62, 54.40),
+    (54.40, 55.02, 54.12, 54.70),
+    (54.70, 55.18, 53.78, 54.20),
+    (54.20, 54.68, 53.96, 54.40),
+    (54.40, 55.00, 53.08, 53.60),
+    (53.60, 54.04, 52.62, 53.00),
+    (53.00, 53.64, 51.34, 51.90),
+    (51.90, 52.26, 51.19, 51.50),
+    (51.50, 52.02, 50.15, 50.60),
+    (50.60, 51.30, 50.25, 50.90),
+    (50.90, 51.38, 49.78, 50.20),
+    (50.20, 50.80, 48.68, 49.20),
+    (49.20, 49.60, 48.35, 48.70),
+    (48.70, 49.22, 48.42, 48.90),
+    (48.90, 49.34, 47.91, 48.30),
+    (48.30, 49.06, 47.98, 48.70),
+    (48.70, 50.12, 48.25, 49.60),
+    (49.60, 50.70, 49.25, 50.30),
+    (50.30, 52.14, 49.74, 51.50),
+    (51.50, 51.94, 50.82, 51.20),
+    (51.20, 52.48, 50.78, 52.00),
+    (52.00, 52.96, 51.69, 52.60),
+    (52.60, 54.16, 52.11, 53.60),
+    (53.60, 54.00, 52.85, 53.20),
+    (53.20, 54.02, 52.92, 53.70),
+    (53.70, 55.08, 53.28, 54.60),
+    (54.60, 54.96, 54.09, 54.40),
+    (54.40, 55.54, 54.02, 55.10),
+    (55.10, 55.72, 54.82, 55.40),
+    (55.40, 55.80, 54.45, 54.80),
+    (54.80, 55.56, 54.48, 55.20),
+];
@@ -0,0 +1,402 @@
+// Price levels from the session calendar (see sessions.rs).  These are built from every tick
+// rather than from the chart's candles, so they're the same whatever the chart's period and
+// however few candles it keeps.  They only know about days the chart has seen ticks for.
+// Days end at the New York close rather than midnight, see sessions::trading_day_number().
+
+use chart::{Candle, PriceSide};
+use indicators::{Indicator, IndicatorDefinition};
+use sessions;
+use sessions::Session;
+use tick::Tick;
+
+// ===== PERIOD RANGE ==============================================================================
+
+#[derive(Clone,Copy)]
+struct PeriodRange {
+    number: i64,
+    high: f32,
+    low: f32,
+    close: f32,
+}
+
+// high, low and close of the current period (day, week, ...) and the one before it
+#[derive(Clone)]
+struct RangeTracker {
+    current: Option<PeriodRange>,
+    previous: Option<PeriodRange>,
+}
+
+impl RangeTracker {
+    fn new() -> RangeTracker {
+        RangeTracker { current: None, previous: None }
+    }
+
+    // returns true when `number` starts a new period
+    fn push(&mut self, number: i64, price: f32) -> bool {
+        let same_period = match self.current {
+            Some(range) => range.number == number,
+            None        => false,
+        };
+
+        if !same_period {
+            self.previous = self.current;
+            self.current  = Some(PeriodRange {
+                number: number,
+                high: price,
+                low: price,
+                close: price,
+            });
+
+            return true;
+        }
+
+        let range = self.current.as_mut().unwrap();
+
+        if price > range.high {
+            range.high = price;
+        }
+
+        if price < range.low {
+            range.low = price;
+        }
+
+        range.close = price;
+
+        false
+    }
+}
+
+// ===== PIVOTS ====================================================================================
+
+#[derive(Clone,Copy)]
+pub enum PivotKind {
+    Classic,
+    Fibonacci,
+    Camarilla,
+}
+
+// Daily pivot points from the previous day's high, low and close.  Classic and Fibonacci pivots
+// have three levels each side, Camarilla four.
+#[derive(Clone)]
+pub struct Pivots {
+    kind: PivotKind,
+    side: PriceSide,
+    days: RangeTracker,
+}
+
+impl Pivots {
+    pub fn new(kind: PivotKind, side: PriceSide) -> Pivots {
+        Pivots { kind: kind, side: side, days: RangeTracker::new() }
+    }
+}
+
+impl Indicator for Pivots {
+    fn update(&mut self, _candles: &Vec<Candle>) {}
+
+    fn update_on_tick(&mut self, tick: &Tick) -> bool {
+        self.days.push(sessions::trading_day_number(tick.time), self.side.pick(tick.bid, tick.ask))
+    }
+
+    // pp, r1, r2, r3[, r4], s1, s2, s3[, s4]
+    fn outputs(&self) -> Vec<f32> {
+        let (high, low, close) = match self.days.previous {
+            Some(day) => (day.high, day.low, day.close),
+            None      => (0.0, 0.0, 0.0),
+        };
+
+        let pivot = (high + low + close) / 3.0;
+        let range = high - low;
+
+        match self.kind {
+            PivotKind::Classic   => vec!(
+                pivot,
+                2.0 * pivot - low,
+                pivot + range,
+                high + 2.0 * (pivot - low),
+                2.0 * pivot - high,
+                pivot - range,
+                low - 2.0 * (high - pivot),
+            ),
+            PivotKind::Fibonacci => vec!(
+                pivot,
+                pivot + 0.382 * range,
+                pivot + 0.618 * range,
+                pivot + range,
+                pivot - 0.382 * range,
+                pivot - 0.618 * range,
+                pivot - range,
+            ),
+            PivotKind::Camarilla => vec!(
+                pivot,
+                close + range * 1.1 / 12.0,
+                close + range * 1.1 / 6.0,
+                close + range * 1.1 / 4.0,
+                close + range * 1.1 / 2.0,
+                close - range * 1.1 / 12.0,
+                close - range * 1.1 / 6.0,
+                close - range * 1.1 / 4.0,
+                close - range * 1.1 / 2.0,
+            ),
+        }
+    }
+
+    fn warmup(&self) -> uint {
+        1
+    }
+
+    // the levels come from a period that has to have completed
+    fn is_ready(&self) -> bool {
+        self.days.previous.is_some()
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_pivots(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(Pivots::new(PivotKind::Classic, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_fibonacci_pivots(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(Pivots::new(PivotKind::Fibonacci, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_camarilla_pivots(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(Pivots::new(PivotKind::Camarilla, definition.source.side)) as Box<Indicator>
+}
+
+// ===== PREVIOUS DAY / WEEK =======================================================================
+
+#[derive(Clone)]
+pub struct PreviousPeriod {
+    weekly: bool,
+    side: PriceSide,
+    periods: RangeTracker,
+}
+
+impl PreviousPeriod {
+    pub fn new(weekly: bool, side: PriceSide) -> PreviousPeriod {
+        PreviousPeriod { weekly: weekly, side: side, periods: RangeTracker::new() }
+    }
+}
+
+impl Indicator for PreviousPeriod {
+    fn update(&mut self, _candles: &Vec<Candle>) {}
+
+    fn update_on_tick(&mut self, tick: &Tick) -> bool {
+        let number = match self.weekly {
+            true  => sessions::week_number(tick.time),
+            false => sessions::trading_day_number(tick.time),
+        };
+
+        self.periods.push(number, self.side.pick(tick.bid, tick.ask))
+    }
+
+    // high, low, close
+    fn outputs(&self) -> Vec<f32> {
+        match self.periods.previous {
+            Some(period) => vec!(period.high, period.low, period.close),
+            None         => vec!(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn warmup(&self) -> uint {
+        1
+    }
+
+    // the levels come from a period that has to have completed
+    fn is_ready(&self) -> bool {
+        self.periods.previous.is_some()
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_previous_day(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(PreviousPeriod::new(false, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_previous_week(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(PreviousPeriod::new(true, definition.source.side)) as Box<Indicator>
+}
+
+// ===== SESSION RANGE =============================================================================
+
+// High and low of the most recently completed session, e.g. the Asian range for breakouts
+// during London.  They change once the session closes.
+#[derive(Clone)]
+pub struct SessionRange {
+    session: Session,
+    side: PriceSide,
+    running: Option<PeriodRange>,
+    completed: Option<PeriodRange>,
+}
+
+impl SessionRange {
+    pub fn new(session: Session, side: PriceSide) -> SessionRange {
+        SessionRange { session: session, side: side, running: None, completed: None }
+    }
+}
+
+impl Indicator for SessionRange {
+    fn update(&mut self, _candles: &Vec<Candle>) {}
+
+    fn update_on_tick(&mut self, tick: &Tick) -> bool {
+        let day = sessions::trading_day_number(tick.time);
+
+        if !self.session.is_open(tick.time) {
+            return match self.running.take() {
+                Some(range) => {
+                    self.completed = Some(range);
+                    true
+                },
+                None => false,
+            };
+        }
+
+        let price = self.side.pick(tick.bid, tick.ask);
+
+        let mut changed = false;
+
+        let mut range = match self.running {
+            Some(range) if range.number == day => range,
+            Some(range) => {
+                // a gap in the ticks skipped the close of the session
+                self.completed = Some(range);
+                changed = true;
+
+                PeriodRange { number: day, high: price, low: price, close: price }
+            },
+            None => PeriodRange { number: day, high: price, low: price, close: price },
+        };
+
+        if price > range.high {
+            range.high = price;
+        }
+
+        if price < range.low {
+            range.low = price;
+        }
+
+        range.close = price;
+
+        self.running = Some(range);
+
+        changed
+    }
+
+    // high, low
+    fn outputs(&self) -> Vec<f32> {
+        match self.completed {
+            Some(range) => vec!(range.high, range.low),
+            None        => vec!(0.0, 0.0),
+        }
+    }
+
+    fn warmup(&self) -> uint {
+        1
+    }
+
+    // there's no range until a session has closed
+    fn is_ready(&self) -> bool {
+        self.completed.is_some()
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_asian_range(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(SessionRange::new(Session::Asian, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_london_range(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(SessionRange::new(Session::London, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_new_york_range(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(SessionRange::new(Session::NewYork, definition.source.side)) as Box<Indicator>
+}
+
+// ===== VWAP ======================================================================================
+
+// There's no traded volume in the tick data, so every tick counts once and this is the average
+// price per tick.  It starts over every trading day, or at the open of `session` if there is
+// one and then keeps its value after the session closes.
+#[derive(Clone)]
+pub struct TickVWAP {
+    session: Option<Session>,
+    side: PriceSide,
+    day: i64,
+    sum: f64,
+    count: uint,
+}
+
+impl TickVWAP {
+    pub fn new(session: Option<Session>, side: PriceSide) -> TickVWAP {
+        TickVWAP { session: session, side: side, day: -1, sum: 0.0, count: 0 }
+    }
+}
+
+impl Indicator for TickVWAP {
+    fn update(&mut self, _candles: &Vec<Candle>) {}
+
+    fn update_on_tick(&mut self, tick: &Tick) -> bool {
+        match self.session {
+            Some(session) if !session.is_open(tick.time) => return false,
+            _                                            => {},
+        }
+
+        let day = sessions::trading_day_number(tick.time);
+
+        if day != self.day {
+            self.day   = day;
+            self.sum   = 0.0;
+            self.count = 0;
+        }
+
+        self.sum   += self.side.pick(tick.bid, tick.ask) as f64;
+        self.count += 1;
+
+        true
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        match self.count {
+            0 => vec!(0.0),
+            n => vec!((self.sum / n as f64) as f32),
+        }
+    }
+
+    fn warmup(&self) -> uint {
+        1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.count > 0
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_vwap(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(TickVWAP::new(None, definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_asian_vwap(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(TickVWAP::new(Some(Session::Asian), definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_london_vwap(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(TickVWAP::new(Some(Session::London), definition.source.side)) as Box<Indicator>
+}
+
+pub fn construct_new_york_vwap(definition: &IndicatorDefinition) -> Box<Indicator> {
+    Box::new(TickVWAP::new(Some(Session::NewYork), definition.source.side)) as Box<Indicator>
+}
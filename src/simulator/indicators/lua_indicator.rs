@@ -0,0 +1,59 @@
+use chart::Candle;
+use indicators::Indicator;
+use lua::{Lua, LuaError};
+
+// A function the strategy script registered with register_indicator().  It's called with the
+// chart's candle table and whatever it returned as state last time, and returns value, state.
+// The state lives in the interpreter, so there's nothing to calculate here.
+#[derive(Clone)]
+pub struct LuaIndicator {
+    name: String,
+    chart: String,
+    function: String,
+    value: f32,
+}
+
+impl LuaIndicator {
+    // `chart` is the Lua name of the chart the indicator is attached to
+    pub fn new(name: &str, chart: &str, function: &str) -> LuaIndicator {
+        LuaIndicator {
+            name: name.to_string(),
+            chart: chart.to_string(),
+            function: function.to_string(),
+            value: 0.0,
+        }
+    }
+}
+
+impl Indicator for LuaIndicator {
+    // needs_lua() keeps the chart from calling this without an interpreter
+    fn update(&mut self, _candles: &Vec<Candle>) {
+        panic!("{} can only be updated by a running strategy", self.name);
+    }
+
+    fn update_with_lua(&mut self, _candles: &Vec<Candle>, lua: &Lua) -> Result<(), LuaError> {
+        self.value = try!(lua.call_indicator(
+            self.function.as_slice(),
+            self.chart.as_slice(),
+            self.name.as_slice(),
+        ));
+
+        Ok(())
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.value)
+    }
+
+    fn warmup(&self) -> uint {
+        1
+    }
+
+    fn needs_lua(&self) -> bool {
+        true
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
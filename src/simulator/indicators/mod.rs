@@ -0,0 +1,641 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::num::Float;
+
+use chart::{Candle, PriceSource};
+use config::VariableKind;
+use lua::{Lua, LuaError};
+use range_bound_variable::RangeBoundVariables;
+use tick::Tick;
+
+use self::lua_indicator::LuaIndicator;
+use self::registry::ParameterKind;
+
+pub mod averages;
+pub mod indicator_utils;
+pub mod levels;
+pub mod lua_indicator;
+pub mod oscillators;
+pub mod patterns;
+pub mod registry;
+pub mod trend;
+pub mod volatility;
+
+// ===== INDICATOR =================================================================================
+
+// Everything a chart needs from an indicator.  The chart keeps track of names, history, inputs
+// and publishing to Lua (see ChartIndicator), so an implementation only does the maths.
+//
+// candles[0] is the incomplete candle and candles[1] the one that just completed.  Indicators
+// that keep running state can read the whole history on the first update and only candles[1]
+// after that (see indicator_utils::new_candle_indexes()).
+pub trait Indicator {
+    // called each time a candle completes
+    fn update(&mut self, candles: &Vec<Candle>);
+
+    // Called instead of update() while the chart belongs to a simulation, with the interpreter
+    // running its strategy.
+    fn update_with_lua(&mut self, candles: &Vec<Candle>, _lua: &Lua) -> Result<(), LuaError> {
+        self.update(candles);
+        Ok(())
+    }
+
+    // Called on every tick the chart processes.  Returns true when the outputs changed and
+    // should be published again before the candle completes.
+    fn update_on_tick(&mut self, _tick: &Tick) -> bool {
+        false
+    }
+
+    // latest value of each output, in the order they were registered in
+    fn outputs(&self) -> Vec<f32>;
+
+    // number of completed candles the chart must hold before the indicator can be calculated
+    fn warmup(&self) -> uint;
+
+    // Whether the outputs mean anything yet.  The chart only asks once the indicator has been
+    // updated with `warmup()` candles, so most indicators are ready by then.
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    // Indicators calling into the strategy's interpreter aren't updated until it's running, and
+    // then only through update_with_lua().
+    fn needs_lua(&self) -> bool {
+        false
+    }
+
+    // charts are cloned for every simulation, so their indicators have to be as well
+    fn box_clone(&self) -> Box<Indicator>;
+}
+
+impl Clone for Box<Indicator> {
+    fn clone(&self) -> Box<Indicator> {
+        self.box_clone()
+    }
+}
+
+// ===== INDICATOR DEFINITION ======================================================================
+
+// An indicator as written in the chart definition, e.g. "bollinger,20,2.5,hlc3_mid".  This is
+// what registered constructors build indicators from.
+#[derive(Clone)]
+pub struct IndicatorDefinition {
+    // the Lua name, for error messages
+    pub name: String,
+
+    pub indicator_type: String,
+    pub params: Vec<f32>,
+
+    // Range-bound variables standing in for parameters, e.g. "sma,$fast_period".  The
+    // parameter is a placeholder until bind() fills in the sampled value.
+    pub variables: Vec<Option<String>>,
+
+    // Single-series indicators (SMA, EMA, RSI, ...) read the source's price directly.  Range
+    // based ones (ATR, stochastic, ...) take highs, lows and closes from the source's side.
+    pub source: PriceSource,
+
+    // "key=value" options the parser doesn't know itself, e.g. "patterns,doji_body=0.05".  Only
+    // the ones the indicator was registered with are allowed.
+    pub options: HashMap<String, f32>,
+}
+
+impl IndicatorDefinition {
+    pub fn new(name: &str, indicator_type: &str, params: Vec<f32>) -> IndicatorDefinition {
+        let variables = Vec::from_elem(params.len(), None);
+
+        IndicatorDefinition {
+            name: name.to_string(),
+            indicator_type: indicator_type.to_string(),
+            params: params,
+            variables: variables,
+            source: PriceSource::close_bid(),
+            options: HashMap::new(),
+        }
+    }
+
+    pub fn has_variables(&self) -> bool {
+        self.variables.iter().any( |v| v.is_some() )
+    }
+
+    // a copy with this iteration's sampled values in place of the variables
+    pub fn bind(&self, vars: &RangeBoundVariables) -> IndicatorDefinition {
+        let mut definition = self.clone();
+
+        for (index, variable) in self.variables.iter().enumerate() {
+            match *variable {
+                Some(ref name) => definition.params[index] = vars.get_number(name.as_slice()),
+                None           => {},
+            }
+        }
+
+        definition.variables = Vec::from_elem(self.params.len(), None);
+
+        definition
+    }
+
+    pub fn option(&self, name: &str, default: f32) -> f32 {
+        match self.options.get(name) {
+            Some(&value) => value,
+            None         => default,
+        }
+    }
+
+    // `known` are the options the indicator was registered with
+    pub fn check_options(&self, known: &[&str]) {
+        for name in self.options.keys() {
+            if !known.iter().any( |&k| k == name.as_slice() ) {
+                panic!("{}: unknown indicator option: {}", self.name, name);
+            }
+        }
+    }
+
+    pub fn param(&self, index: uint) -> f32 {
+        let param = self.params[index];
+
+        if param <= 0.0 {
+            panic!("{}: parameters must be > 0, got {}", self.name, param);
+        }
+
+        param
+    }
+
+    pub fn period(&self, index: uint) -> uint {
+        let param = self.param(index);
+
+        if param != param.floor() {
+            panic!("{}: periods must be whole numbers, got {}", self.name, param);
+        }
+
+        param as uint
+    }
+}
+
+// ===== CHART INDICATOR ===========================================================================
+
+#[derive(Clone)]
+struct IndicatorInput {
+    name: String,
+
+    // resolved by Chart::resolve_indicator_inputs()
+    index: uint,
+    output: uint,
+}
+
+// An indicator attached to a chart along with everything the chart does on its behalf
+#[derive(Clone)]
+pub struct ChartIndicator {
+    name: String,
+
+    // None for Lua indicators, which aren't in the registry
+    definition: Option<IndicatorDefinition>,
+    output_suffixes: Vec<&'static str>,
+
+    // The indicator as first constructed, which bind_variables() starts over from.  Indicators
+    // with variable parameters are constructed again for every binding instead.
+    pristine: Option<Box<Indicator>>,
+    indicator: Option<Box<Indicator>>,
+
+    // set by the first update, which the chart holds back until there are enough candles
+    updated: bool,
+
+    // latest value of each output, see output_suffixes
+    values: Vec<f32>,
+
+    // previous values of each output, newest first.  Nothing is kept when the length is 0.
+    // Indicators that feed other indicators may keep more than they publish to Lua.
+    history_length: uint,
+    published_history_length: uint,
+    history: Vec<Vec<f32>>,
+
+    // another indicator's output on the same chart used instead of the chart's candles
+    input: Option<IndicatorInput>,
+}
+
+impl ChartIndicator {
+    fn new(name: &str,
+           definition: Option<IndicatorDefinition>,
+           output_suffixes: Vec<&'static str>,
+           pristine: Option<Box<Indicator>>) -> ChartIndicator {
+        let mut indi = ChartIndicator {
+            name: name.to_string(),
+            definition: definition,
+            output_suffixes: output_suffixes,
+            pristine: pristine.clone(),
+            indicator: None,
+            updated: false,
+            values: vec!(),
+            history_length: 0,
+            published_history_length: 0,
+            history: vec!(),
+            input: None,
+        };
+
+        match pristine {
+            Some(indicator) => indi.reset(indicator),
+            None            => {}, // waiting for bind_variables()
+        }
+
+        indi
+    }
+
+    // builds the indicator the definition names from the registry
+    pub fn from_definition(definition: IndicatorDefinition) -> ChartIndicator {
+        let name = definition.name.clone();
+        let indicator_type = definition.indicator_type.clone();
+
+        let registered = match registry::get().lookup(indicator_type.as_slice()) {
+            Some(registered) => registered,
+            None             => panic!("unknown indicator type: {}", indicator_type),
+        };
+
+        let parameters = registered.parameters.len();
+        let outputs = registered.outputs.clone();
+
+        if definition.params.len() != parameters {
+            panic!(
+                "indicator {} takes {} parameter(s), got {}",
+                indicator_type,
+                parameters,
+                definition.params.len(),
+            );
+        }
+
+        // so a typo like "histroy=5" doesn't silently run with the default
+        definition.check_options(registered.options.as_slice());
+
+        // placeholders for variables can't be validated until they're bound
+        let pristine = match definition.has_variables() {
+            true  => None,
+            false => Some(registry::get().create(&definition)),
+        };
+
+        ChartIndicator::new(name.as_slice(), Some(definition), outputs, pristine)
+    }
+
+    // `chart` is the Lua name of the chart the indicator is attached to
+    pub fn new_lua(name: &str, chart: &str, function: &str) -> ChartIndicator {
+        let indicator = Box::new(LuaIndicator::new(name, chart, function)) as Box<Indicator>;
+        ChartIndicator::new(name, None, vec!(""), Some(indicator))
+    }
+
+    // throws away all calculated state
+    fn reset(&mut self, indicator: Box<Indicator>) {
+        let outputs = self.output_suffixes.len();
+
+        self.indicator = Some(indicator);
+        self.updated   = false;
+        self.values    = Vec::from_elem(outputs, 0.0f32);
+        self.history   = Vec::from_elem(outputs, vec!());
+    }
+
+    pub fn has_variables(&self) -> bool {
+        match self.definition {
+            Some(ref definition) => definition.has_variables(),
+            None                 => false,
+        }
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.indicator.is_some()
+    }
+
+    // whether the indicator has warmed up and its outputs can be traded on
+    pub fn is_ready(&self) -> bool {
+        match self.indicator {
+            Some(ref indicator) => self.updated && indicator.is_ready(),
+            None                => false,
+        }
+    }
+
+    pub fn needs_lua(&self) -> bool {
+        match self.indicator {
+            Some(ref indicator) => indicator.needs_lua(),
+            None                => false,
+        }
+    }
+
+    // Checks the variables standing in for parameters over their whole range, so one the
+    // optimizer could pick that doesn't work fails before the run instead of part way through.
+    // `chart_candles` is the number of completed candles the chart holds.
+    //
+    // Checks between parameters (see registry::set_check()) can fail for some samples but not
+    // others, e.g. MACD with $fast and $slow overlapping.  Those only fail when every
+    // combination of the bounds does.  The other samples fail their iteration when they're
+    // bound, see bind_variables().
+    pub fn check_variables(&self,
+                           vars: &RangeBoundVariables,
+                           chart_candles: i32) -> Result<(), String> {
+        let definition = match self.definition {
+            Some(ref definition) if definition.has_variables() => definition,
+            _                                                  => return Ok(()),
+        };
+
+        let kinds = match registry::get().lookup(definition.indicator_type.as_slice()) {
+            Some(registered) => registered.parameters.clone(),
+            None             => return Err(format!("unknown indicator type: {}", self.name)),
+        };
+
+        // (parameter index, lower, upper) of each variable
+        let mut bounds: Vec<(uint, f32, f32)> = vec!();
+
+        for (index, variable) in definition.variables.iter().enumerate() {
+            let name = match *variable {
+                Some(ref name) => name.as_slice(),
+                None           => continue,
+            };
+
+            let (lower, upper) = match (vars.kind_of(name), kinds[index]) {
+                (Some(VariableKind::Int(lower, upper)), _) => (lower as f32, upper as f32),
+                (Some(VariableKind::Float(..)), ParameterKind::Period) => return Err(format!(
+                    "{}: parameter {} is a period, so ${} must be an int",
+                    self.name,
+                    index + 1,
+                    name
+                )),
+                (Some(VariableKind::Float(lower, upper)), _) => (lower, upper),
+                (Some(VariableKind::Bool), _) => return Err(format!(
+                    "{}: ${} is a bool, parameters must be numbers",
+                    self.name,
+                    name
+                )),
+                (None, _) => return Err(format!("{}: unknown variable ${}", self.name, name)),
+            };
+
+            if lower <= 0.0 {
+                return Err(format!(
+                    "{}: parameter {} must be > 0, but ${} can be {}",
+                    self.name,
+                    index + 1,
+                    name,
+                    lower
+                ));
+            }
+
+            bounds.push((index, lower, upper));
+        }
+
+        let mut last_error = String::new();
+        let mut valid = false;
+        let mut required = 0u;
+
+        // every combination of lower and upper bounds
+        for combination in range(0u, 1 << bounds.len()) {
+            let mut sample = definition.clone();
+
+            for (bit, &(index, lower, upper)) in bounds.iter().enumerate() {
+                sample.params[index] = match combination & (1 << bit) {
+                    0 => lower,
+                    _ => upper,
+                };
+            }
+
+            sample.variables = Vec::from_elem(definition.params.len(), None);
+
+            match registry::get().check(&sample) {
+                Ok(())     => {},
+                Err(error) => {
+                    last_error = error;
+                    continue;
+                },
+            }
+
+            valid = true;
+            required = cmp::max(required, registry::get().create(&sample).warmup());
+        }
+
+        if !valid {
+            return Err(format!(
+                "{}: no combination of its variables' bounds works: {}",
+                self.name,
+                last_error
+            ));
+        }
+
+        // an indicator reading another one waits for that one's history instead of the chart's
+        if self.input.is_none() && required as i32 > chart_candles {
+            return Err(format!(
+                "{}: needs up to {} candles with its variables' ranges but the chart only has {}",
+                self.name,
+                required,
+                chart_candles
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Fills in the variable parameters with this iteration's sampled values and starts over.
+    // The next update primes the indicator from the chart history again.  Fails when the
+    // sampled values don't work together, see registry::set_check().
+    pub fn bind_variables(&mut self, vars: &RangeBoundVariables) -> Result<(), String> {
+        let indicator = match self.pristine {
+            Some(ref pristine) => pristine.clone(),
+            None               => {
+                let definition = match self.definition {
+                    Some(ref definition) => definition.bind(vars),
+                    None                 => panic!("indicator {} has no definition", self.name),
+                };
+
+                try!(registry::get().check(&definition));
+                registry::get().create(&definition)
+            },
+        };
+
+        self.reset(indicator);
+
+        Ok(())
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_slice()
+    }
+
+    pub fn set_history_length(&mut self, length: uint) {
+        self.published_history_length = length;
+        self.retain_history(length);
+    }
+
+    // keeps at least `length` values of each output without publishing them
+    pub fn retain_history(&mut self, length: uint) {
+        if length > self.history_length {
+            self.history_length = length;
+        }
+    }
+
+    // `name` is the full Lua name of the other indicator's output
+    pub fn set_input(&mut self, name: &str) {
+        self.input = Some(IndicatorInput { name: name.to_string(), index: 0, output: 0 });
+    }
+
+    pub fn get_input_name(&self) -> Option<&str> {
+        match self.input {
+            Some(ref input) => Some(input.name.as_slice()),
+            None            => None,
+        }
+    }
+
+    pub fn set_input_index(&mut self, index: uint, output: uint) {
+        match self.input {
+            Some(ref mut input) => {
+                input.index  = index;
+                input.output = output;
+            },
+            None => panic!("indicator {} has no input", self.name),
+        }
+    }
+
+    // (indicator index, output index) of the input, once resolved
+    pub fn get_input_index(&self) -> Option<(uint, uint)> {
+        match self.input {
+            Some(ref input) => Some((input.index, input.output)),
+            None            => None,
+        }
+    }
+
+    // Presents one output's history as flat candles so any indicator can consume it.  Like a
+    // chart, index 0 stands in for the incomplete candle.  Returns None until `count` values
+    // have been recorded.
+    pub fn history_as_candles(&self, output: uint, count: uint) -> Option<Vec<Candle>> {
+        let series = &self.history[output];
+
+        if series.len() < count || 0 == series.len() {
+            return None;
+        }
+
+        let mut candles = vec!(Candle::from_value(series[0]));
+
+        for &value in series.iter() {
+            candles.push(Candle::from_value(value));
+        }
+
+        Some(candles)
+    }
+
+    // number of completed candles the chart must hold before the indicator can be calculated
+    pub fn candles_required(&self) -> i32 {
+        match self.indicator {
+            Some(ref indicator) => indicator.warmup() as i32,
+            None                => panic!("indicator {} isn't bound yet", self.name),
+        }
+    }
+
+    // latest value of each output, see output_suffixes
+    pub fn get_values(&self) -> &Vec<f32> {
+        &self.values
+    }
+
+    pub fn output_suffixes(&self) -> &Vec<&'static str> {
+        &self.output_suffixes
+    }
+
+    // Single-output indicators publish under their own name.  The others publish one Lua global
+    // per output with the suffix appended, e.g. "candlestick_M1_macd_12_26_9_signal".
+    pub fn output_name(&self, index: uint) -> String {
+        match self.output_suffixes[index] {
+            ""     => self.name.clone(),
+            suffix => format!("{}_{}", self.name, suffix),
+        }
+    }
+
+    // `lua` is the interpreter of the simulation the chart belongs to, if any
+    pub fn update(&mut self, candles: &Vec<Candle>, lua: Option<&Lua>) -> Result<(), LuaError> {
+        self.values = match self.indicator {
+            Some(ref mut indicator) => {
+                match lua {
+                    Some(lua) => try!(indicator.update_with_lua(candles, lua)),
+                    None      => {
+                        // there's no interpreter to call outside a simulation
+                        if indicator.needs_lua() {
+                            return Ok(());
+                        }
+
+                        indicator.update(candles);
+                    },
+                }
+
+                indicator.outputs()
+            },
+            None => return Ok(()),
+        };
+
+        self.updated = true;
+
+        self.record_history();
+
+        // println!("Updating {} -> {}", self.get_name(), self.values);
+        match lua {
+            Some(lua) => self.send_to_lua(lua),
+            None      => {},
+        }
+
+        Ok(())
+    }
+
+    pub fn update_on_tick(&mut self, tick: &Tick, lua: Option<&Lua>) {
+        let changed = match self.indicator {
+            Some(ref mut indicator) => indicator.update_on_tick(tick),
+            None                    => false,
+        };
+
+        if !changed {
+            return;
+        }
+
+        self.values = self.indicator.as_ref().unwrap().outputs();
+
+        match lua {
+            Some(lua) => self.send_to_lua(lua),
+            None      => {},
+        }
+    }
+
+    fn record_history(&mut self) {
+        if 0 == self.history_length {
+            return;
+        }
+
+        for (series, &value) in self.history.iter_mut().zip(self.values.iter()) {
+            if series.len() >= self.history_length {
+                series.pop();
+            }
+
+            series.insert(0, value);
+        }
+    }
+
+    // Indexed like the chart's candles: {name}_history[i] is the value calculated when candle i
+    // completed, so index 1 is the most recently completed candle.  Index 0 stands for the
+    // incomplete candle and is the plain global's value, which indicators updated on every tick
+    // move before the candle completes.  "{name}_ready" tells the strategy whether the values
+    // can be traded on yet.
+    fn send_to_lua(&self, lua: &Lua) {
+        lua.register_boolean(format!("{}_ready", self.name).as_slice(), self.is_ready());
+
+        for i in range(0, self.values.len()) {
+            let name = self.output_name(i);
+
+            lua.register_number(name.as_slice(), self.values[i]);
+
+            if 0 == self.published_history_length {
+                continue;
+            }
+
+            let series = &self.history[i];
+            let length = cmp::min(series.len(), self.published_history_length);
+
+            lua.create_table(length as i32 + 1);
+
+            lua.push_table_integer(0);
+            lua.push_table_number(self.values[i]);
+            lua.set_table(-3);
+
+            for (index, &value) in series.iter().take(length).enumerate() {
+                lua.push_table_integer(index as i32 + 1);
+                lua.push_table_number(value);
+                lua.set_table(-3);
+            }
+
+            lua.finalize_table(format!("{}_history", name).as_slice());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::num::Float;
+
+use chart::{Candle, PriceSide, PriceSource};
+use indicators::{Indicator, IndicatorDefinition};
+use indicators::indicator_utils;
+use indicators::indicator_utils::Smoother;
+
+// ===== RSI =======================================================================================
+
+// Wilder's RSI, seeded with the simple average of the oldest `period` changes on the chart the
+// same way charting platforms warm it up.
+#[derive(Clone)]
+pub struct RelativeStrengthIndex {
+    period: uint,
+    source: PriceSource,
+    primed: bool,
+    gain: Smoother,
+    loss: Smoother,
+}
+
+impl RelativeStrengthIndex {
+    pub fn new(period: uint, source: PriceSource) -> RelativeStrengthIndex {
+        RelativeStrengthIndex {
+            period: period,
+            source: source,
+            primed: false,
+            gain: Smoother::wilder(period),
+            loss: Smoother::wilder(period),
+        }
+    }
+}
+
+impl Indicator for RelativeStrengthIndex {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            // the oldest candle has nothing to compare against
+            if i + 1 >= candles.len() {
+                continue;
+            }
+
+            let change = self.source.value(&candles[i]) - self.source.value(&candles[i + 1]);
+
+            self.gain.push(change.max(0.0));
+            self.loss.push((-change).max(0.0));
+        }
+
+        self.primed = true;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        let gain = self.gain.value;
+        let loss = self.loss.value;
+
+        if 0.0 == loss {
+            return match 0.0 == gain {
+                true  => vec!(50.0),
+                false => vec!(100.0),
+            };
+        }
+
+        vec!(100.0 - (100.0 / (1.0 + gain / loss)))
+    }
+
+    fn warmup(&self) -> uint {
+        self.period + 1
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_rsi(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let rsi = RelativeStrengthIndex::new(definition.period(0), definition.source);
+    Box::new(rsi) as Box<Indicator>
+}
+
+// ===== STOCHASTIC ================================================================================
+
+fn stochastic_k(candles: &Vec<Candle>, side: PriceSide, offset: uint, period: uint) -> f32 {
+    let high = indicator_utils::highest_high(candles, side, offset, period);
+    let low  = indicator_utils::lowest_low(candles, side, offset, period);
+
+    if high == low {
+        return 50.0;
+    }
+
+    100.0 * (candles[offset].close(side) - low) / (high - low)
+}
+
+#[derive(Clone)]
+pub struct Stochastic {
+    k_period: uint,
+    d_period: uint,
+    side: PriceSide,
+    k: f32,
+    d: f32,
+}
+
+impl Stochastic {
+    pub fn new(k_period: uint, d_period: uint, side: PriceSide) -> Stochastic {
+        Stochastic { k_period: k_period, d_period: d_period, side: side, k: 0.0, d: 0.0 }
+    }
+}
+
+impl Indicator for Stochastic {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        self.k = stochastic_k(candles, self.side, 1, self.k_period);
+
+        // %D is the simple average of the last d_period %K values
+        let mut d = 0.0f32;
+
+        for offset in range(1, self.d_period + 1) {
+            d += stochastic_k(candles, self.side, offset, self.k_period);
+        }
+
+        self.d = d / self.d_period as f32;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.k, self.d)
+    }
+
+    fn warmup(&self) -> uint {
+        self.k_period + self.d_period - 1
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_stochastic(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let stochastic = Stochastic::new(
+        definition.period(0),
+        definition.period(1),
+        definition.source.side,
+    );
+
+    Box::new(stochastic) as Box<Indicator>
+}
+
+// ===== CCI =======================================================================================
+
+#[derive(Clone)]
+pub struct CommodityChannelIndex {
+    period: uint,
+    side: PriceSide,
+    value: f32,
+}
+
+impl CommodityChannelIndex {
+    pub fn new(period: uint, side: PriceSide) -> CommodityChannelIndex {
+        CommodityChannelIndex { period: period, side: side, value: 0.0 }
+    }
+}
+
+impl Indicator for CommodityChannelIndex {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        let period = self.period;
+        let side   = self.side;
+
+        let mut mean = 0.0f32;
+
+        for i in range(1, period + 1) {
+            mean += indicator_utils::typical_price(&candles[i], side);
+        }
+
+        mean /= period as f32;
+
+        let mut deviation = 0.0f32;
+
+        for i in range(1, period + 1) {
+            deviation += (indicator_utils::typical_price(&candles[i], side) - mean).abs();
+        }
+
+        deviation /= period as f32;
+
+        let latest = indicator_utils::typical_price(&candles[1], side);
+
+        self.value = match 0.0 == deviation {
+            true  => 0.0,
+            false => (latest - mean) / (0.015 * deviation),
+        };
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.value)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_cci(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let cci = CommodityChannelIndex::new(definition.period(0), definition.source.side);
+    Box::new(cci) as Box<Indicator>
+}
+
+// ===== WILLIAMS %R ===============================================================================
+
+#[derive(Clone)]
+pub struct WilliamsR {
+    period: uint,
+    side: PriceSide,
+    value: f32,
+}
+
+impl WilliamsR {
+    pub fn new(period: uint, side: PriceSide) -> WilliamsR {
+        WilliamsR { period: period, side: side, value: 0.0 }
+    }
+}
+
+impl Indicator for WilliamsR {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        let high = indicator_utils::highest_high(candles, self.side, 1, self.period);
+        let low  = indicator_utils::lowest_low(candles, self.side, 1, self.period);
+
+        self.value = match high == low {
+            true  => -50.0,
+            false => -100.0 * (high - candles[1].close(self.side)) / (high - low),
+        };
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.value)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_williams_r(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let williams = WilliamsR::new(definition.period(0), definition.source.side);
+    Box::new(williams) as Box<Indicator>
+}
+
+// ===== MACD ======================================================================================
+
+#[derive(Clone)]
+pub struct MACD {
+    slow_period: uint,
+    signal_period: uint,
+    source: PriceSource,
+    primed: bool,
+    fast: Smoother,
+    slow: Smoother,
+    signal: Smoother,
+}
+
+impl MACD {
+    pub fn new(fast: uint, slow: uint, signal: uint, source: PriceSource) -> MACD {
+        if fast >= slow {
+            panic!("MACD fast period ({}) must be < slow period ({})", fast, slow);
+        }
+
+        MACD {
+            slow_period: slow,
+            signal_period: signal,
+            source: source,
+            primed: false,
+            fast: Smoother::ema(fast),
+            slow: Smoother::ema(slow),
+            signal: Smoother::ema(signal),
+        }
+    }
+}
+
+impl Indicator for MACD {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            let price = self.source.value(&candles[i]);
+            let line  = self.fast.push(price) - self.slow.push(price);
+
+            // the signal line only starts once the slow average has a full period behind it
+            if self.slow.is_seeded() {
+                self.signal.push(line);
+            }
+        }
+
+        self.primed = true;
+    }
+
+    // line, signal, histogram
+    fn outputs(&self) -> Vec<f32> {
+        let line   = self.fast.value - self.slow.value;
+        let signal = self.signal.value;
+
+        vec!(line, signal, line - signal)
+    }
+
+    fn warmup(&self) -> uint {
+        self.slow_period + self.signal_period - 1
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_macd(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let macd = MACD::new(
+        definition.period(0),
+        definition.period(1),
+        definition.period(2),
+        definition.source,
+    );
+
+    Box::new(macd) as Box<Indicator>
+}
+
+pub fn check_macd(definition: &IndicatorDefinition) -> Result<(), String> {
+    let (fast, slow) = (definition.params[0], definition.params[1]);
+
+    match fast < slow {
+        true  => Ok(()),
+        false => Err(format!(
+            "{}: MACD fast period ({}) must be < slow period ({})",
+            definition.name,
+            fast,
+            slow
+        )),
+    }
+}
@@ -0,0 +1,251 @@
+use std::num::Float;
+
+use chart::{Candle, PriceSide};
+use indicators::{Indicator, IndicatorDefinition};
+
+// Candlestick patterns on the most recently completed candles.  Each output is 0 when the
+// pattern isn't there.  Directional patterns are 1 when bullish and -1 when bearish, the others
+// are just 1.
+//
+//   engulfing    the candle's body engulfs the opposite-coloured body before it
+//   pin_bar      a small body with one long wick: 1 for a hammer, -1 for a shooting star
+//   doji         open and close (almost) the same
+//   inside_bar   the candle's range is inside the previous candle's
+//   outside_bar  the candle's range contains the previous candle's, signed by its direction
+//   star         1 for a morning star, -1 for an evening star
+//
+// The thresholds are fractions of the candle's high-low range unless noted and can be changed
+// with options in the chart definition, e.g. "patterns,doji_body=0.05,pin_wick=0.7".
+#[derive(Clone)]
+pub struct CandlePatterns {
+    side: PriceSide,
+
+    // body / range at or below which a candle is a doji
+    doji_body: f32,
+
+    // largest body / range and smallest long wick / range of a pin bar
+    pin_body: f32,
+    pin_wick: f32,
+
+    // smallest engulfing body / engulfed body
+    engulfing_body: f32,
+
+    // smallest body / range of the outer candles of a star, largest of the middle one
+    star_body: f32,
+    star_middle: f32,
+
+    values: Vec<f32>,
+}
+
+// registered with the indicator, see IndicatorRegistry::register_with_options()
+pub static OPTIONS: [&'static str; 6] = [
+    "doji_body",
+    "pin_body",
+    "pin_wick",
+    "engulfing_body",
+    "star_body",
+    "star_middle",
+];
+
+// ===== GLOBAL FUNCTIONS ==========================================================================
+
+fn body(candle: &Candle, side: PriceSide) -> f32 {
+    (candle.close(side) - candle.open(side)).abs()
+}
+
+fn candle_range(candle: &Candle, side: PriceSide) -> f32 {
+    candle.high(side) - candle.low(side)
+}
+
+fn is_bullish(candle: &Candle, side: PriceSide) -> bool {
+    candle.close(side) > candle.open(side)
+}
+
+fn is_bearish(candle: &Candle, side: PriceSide) -> bool {
+    candle.close(side) < candle.open(side)
+}
+
+// body / range, or 0 for a candle that never moved
+fn body_ratio(candle: &Candle, side: PriceSide) -> f32 {
+    match 0.0 == candle_range(candle, side) {
+        true  => 0.0,
+        false => body(candle, side) / candle_range(candle, side),
+    }
+}
+
+fn direction(bullish: bool) -> f32 {
+    match bullish {
+        true  => 1.0,
+        false => -1.0,
+    }
+}
+
+// ===== CANDLE PATTERNS ===========================================================================
+
+impl CandlePatterns {
+    pub fn new(side: PriceSide) -> CandlePatterns {
+        CandlePatterns {
+            side: side,
+            doji_body: 0.1,
+            pin_body: 0.3,
+            pin_wick: 0.6,
+            engulfing_body: 1.0,
+            star_body: 0.5,
+            star_middle: 0.3,
+            values: Vec::from_elem(6, 0.0f32),
+        }
+    }
+
+    fn engulfing(&self, candle: &Candle, previous: &Candle) -> f32 {
+        let side = self.side;
+
+        let bullish = is_bullish(candle, side) && is_bearish(previous, side);
+        let bearish = is_bearish(candle, side) && is_bullish(previous, side);
+
+        if !bullish && !bearish {
+            return 0.0;
+        }
+
+        let top    = candle.open(side).max(candle.close(side));
+        let bottom = candle.open(side).min(candle.close(side));
+
+        let previous_top    = previous.open(side).max(previous.close(side));
+        let previous_bottom = previous.open(side).min(previous.close(side));
+
+        if top < previous_top || bottom > previous_bottom {
+            return 0.0;
+        }
+
+        if body(candle, side) < self.engulfing_body * body(previous, side) {
+            return 0.0;
+        }
+
+        direction(bullish)
+    }
+
+    fn pin_bar(&self, candle: &Candle) -> f32 {
+        let side = self.side;
+        let size = candle_range(candle, side);
+
+        if 0.0 == size || body_ratio(candle, side) > self.pin_body {
+            return 0.0;
+        }
+
+        let top    = candle.open(side).max(candle.close(side));
+        let bottom = candle.open(side).min(candle.close(side));
+
+        let upper_wick = (candle.high(side) - top) / size;
+        let lower_wick = (bottom - candle.low(side)) / size;
+
+        if lower_wick >= self.pin_wick && lower_wick > upper_wick {
+            1.0
+        } else if upper_wick >= self.pin_wick && upper_wick > lower_wick {
+            -1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn doji(&self, candle: &Candle) -> f32 {
+        let side = self.side;
+
+        match 0.0 != candle_range(candle, side) && body_ratio(candle, side) <= self.doji_body {
+            true  => 1.0,
+            false => 0.0,
+        }
+    }
+
+    fn inside_bar(&self, candle: &Candle, previous: &Candle) -> f32 {
+        let side = self.side;
+
+        match candle.high(side) < previous.high(side) && candle.low(side) > previous.low(side) {
+            true  => 1.0,
+            false => 0.0,
+        }
+    }
+
+    fn outside_bar(&self, candle: &Candle, previous: &Candle) -> f32 {
+        let side = self.side;
+
+        if candle.high(side) <= previous.high(side) || candle.low(side) >= previous.low(side) {
+            return 0.0;
+        }
+
+        match candle.close(side) == candle.open(side) {
+            true  => 0.0,
+            false => direction(is_bullish(candle, side)),
+        }
+    }
+
+    // `first` is the oldest of the three candles
+    fn star(&self, first: &Candle, middle: &Candle, last: &Candle) -> f32 {
+        let side = self.side;
+
+        if body_ratio(first, side) < self.star_body || body_ratio(last, side) < self.star_body {
+            return 0.0;
+        }
+
+        if body_ratio(middle, side) > self.star_middle {
+            return 0.0;
+        }
+
+        let first_midpoint = (first.open(side) + first.close(side)) / 2.0;
+        let middle_top     = middle.open(side).max(middle.close(side));
+        let middle_bottom  = middle.open(side).min(middle.close(side));
+
+        // the last candle has to win back at least half of the first one's body
+        if is_bearish(first, side) && is_bullish(last, side) &&
+           middle_top <= first.close(side) && last.close(side) > first_midpoint {
+            return 1.0;
+        }
+
+        if is_bullish(first, side) && is_bearish(last, side) &&
+           middle_bottom >= first.close(side) && last.close(side) < first_midpoint {
+            return -1.0;
+        }
+
+        0.0
+    }
+}
+
+impl Indicator for CandlePatterns {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        let candle   = &candles[1];
+        let previous = &candles[2];
+
+        self.values = vec!(
+            self.engulfing(candle, previous),
+            self.pin_bar(candle),
+            self.doji(candle),
+            self.inside_bar(candle, previous),
+            self.outside_bar(candle, previous),
+            self.star(&candles[3], previous, candle),
+        );
+    }
+
+    // engulfing, pin_bar, doji, inside_bar, outside_bar, star
+    fn outputs(&self) -> Vec<f32> {
+        self.values.clone()
+    }
+
+    fn warmup(&self) -> uint {
+        3
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_patterns(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let mut patterns = CandlePatterns::new(definition.source.side);
+
+    patterns.doji_body      = definition.option("doji_body", patterns.doji_body);
+    patterns.pin_body       = definition.option("pin_body", patterns.pin_body);
+    patterns.pin_wick       = definition.option("pin_wick", patterns.pin_wick);
+    patterns.engulfing_body = definition.option("engulfing_body", patterns.engulfing_body);
+    patterns.star_body      = definition.option("star_body", patterns.star_body);
+    patterns.star_middle    = definition.option("star_middle", patterns.star_middle);
+
+    Box::new(patterns) as Box<Indicator>
+}
@@ -0,0 +1,254 @@
+// Maps the indicator names used in chart definitions ("sma", "bollinger", ...) to constructors.
+// The registry is built once and read-only after that.  A crate using the simulator as a library
+// adds its own indicators as a setup step, before anything reads the registry:
+//
+//     let mut indicators = IndicatorRegistry::with_builtins();
+//     indicators.register("my_band", &[Period, Positive], &["upper", "lower"], construct_my_band);
+//     registry::install(indicators);
+//
+// Without install() the first get() installs the built-in indicators on their own.
+//
+// Indicators taking "key=value" options use register_with_options() to list them.  Any other
+// option in a chart definition is an error.  Ones whose parameters depend on each other, like
+// MACD's fast and slow periods, add a check with set_check().
+
+use std::collections::HashMap;
+use std::mem;
+use std::num::Float;
+use std::sync::{Once, ONCE_INIT};
+
+use indicators::{Indicator, IndicatorDefinition};
+use indicators::averages;
+use indicators::levels;
+use indicators::oscillators;
+use indicators::patterns;
+use indicators::trend;
+use indicators::volatility;
+
+use self::ParameterKind::{Period, Positive};
+
+// written once under INSTALLED and never again, so handing out shared references is safe
+static INSTALLED: Once = ONCE_INIT;
+static mut REGISTRY: *const IndicatorRegistry = 0 as *const IndicatorRegistry;
+
+pub type IndicatorConstructor = fn(&IndicatorDefinition) -> Box<Indicator>;
+
+// Checks between parameters that the ParameterKinds can't express.  Variables are bound to new
+// values for every iteration, so a failing check fails that iteration instead of panicking in
+// the constructor.
+pub type ParameterCheck = fn(&IndicatorDefinition) -> Result<(), String>;
+
+// What a parameter has to be.  Variables standing in for one are checked against it over their
+// whole range before the optimizer starts.
+#[derive(Clone,Copy,PartialEq,Show)]
+pub enum ParameterKind {
+    Period,   // a whole number > 0, see IndicatorDefinition::period()
+    Positive, // any number > 0, see IndicatorDefinition::param()
+}
+
+pub struct RegisteredIndicator {
+    pub parameters: Vec<ParameterKind>,
+
+    // Single-output indicators use [""] and publish under their own name.  The others publish
+    // one Lua global per output with the suffix appended, e.g. "_upper".
+    pub outputs: Vec<&'static str>,
+
+    // "key=value" options it takes besides name= and history=, see IndicatorDefinition::option()
+    pub options: Vec<&'static str>,
+
+    pub constructor: IndicatorConstructor,
+
+    // see set_check()
+    pub check: Option<ParameterCheck>,
+}
+
+pub struct IndicatorRegistry {
+    indicators: HashMap<String, RegisteredIndicator>,
+}
+
+// ===== GLOBAL FUNCTIONS ==========================================================================
+
+pub fn get() -> &'static IndicatorRegistry {
+    INSTALLED.call_once( || unsafe { REGISTRY = leak(IndicatorRegistry::with_builtins()) } );
+
+    unsafe {
+        &*REGISTRY
+    }
+}
+
+// Makes `registry` the one get() returns.  Panics when it's called a second time or after the
+// first get(), since the indicators already created would come from a different registry.
+pub fn install(registry: IndicatorRegistry) {
+    let mut pending = Some(registry);
+
+    INSTALLED.call_once( || unsafe { REGISTRY = leak(pending.take().unwrap()) } );
+
+    if pending.is_some() {
+        panic!("the indicator registry is already in use, install() it before charts are parsed");
+    }
+}
+
+// lives for the rest of the program
+fn leak(registry: IndicatorRegistry) -> *const IndicatorRegistry {
+    unsafe {
+        mem::transmute(Box::new(registry))
+    }
+}
+
+// ===== INDICATOR REGISTRY ========================================================================
+
+impl IndicatorRegistry {
+    pub fn new() -> IndicatorRegistry {
+        IndicatorRegistry { indicators: HashMap::new() }
+    }
+
+    pub fn with_builtins() -> IndicatorRegistry {
+        let mut registry = IndicatorRegistry::new();
+        registry.register_builtins();
+        registry
+    }
+
+    fn register_builtins(&mut self) {
+        let single: &[&'static str] = &[""];
+        let bands:  &[&'static str] = &["upper", "middle", "lower"];
+        let macd:   &[&'static str] = &["line", "signal", "histogram"];
+        let hlc:    &[&'static str] = &["high", "low", "close"];
+        let range:  &[&'static str] = &["high", "low"];
+        let stoch:  &[&'static str] = &["k", "d"];
+        let pivots: &[&'static str] = &["pp", "r1", "r2", "r3", "s1", "s2", "s3"];
+        let camarilla: &[&'static str] = &["pp", "r1", "r2", "r3", "r4", "s1", "s2", "s3", "s4"];
+        let candle_patterns: &[&'static str] = &[
+            "engulfing",
+            "pin_bar",
+            "doji",
+            "inside_bar",
+            "outside_bar",
+            "star",
+        ];
+
+        let none:    &[ParameterKind] = &[];
+        let period:  &[ParameterKind] = &[Period];
+        let keltner: &[ParameterKind] = &[Period, Period, Positive];
+
+        self.register("sma",       period, single, averages::construct_sma);
+        self.register("ema",       period, single, averages::construct_ema);
+
+        self.register("rsi",       period, single, oscillators::construct_rsi);
+        self.register("stoch",     &[Period, Period], stoch, oscillators::construct_stochastic);
+        self.register("cci",       period, single, oscillators::construct_cci);
+        self.register("williams",  period, single, oscillators::construct_williams_r);
+        self.register("macd",      &[Period, Period, Period], macd, oscillators::construct_macd);
+        self.set_check("macd", oscillators::check_macd);
+
+        self.register("atr",       period, single, volatility::construct_atr);
+        self.register("bollinger", &[Period, Positive], bands, volatility::construct_bollinger);
+        self.register("keltner",   keltner, bands,  volatility::construct_keltner);
+        self.register("donchian",  period, bands,  volatility::construct_donchian);
+
+        self.register("adx",       period, &["adx", "plus_di", "minus_di"], trend::construct_adx);
+        self.register("psar",      &[Positive, Positive], single, trend::construct_parabolic_sar);
+        self.set_check("psar", trend::check_parabolic_sar);
+
+        self.register_with_options(
+            "patterns",
+            none,
+            candle_patterns,
+            &patterns::OPTIONS,
+            patterns::construct_patterns,
+        );
+
+        self.register("pivots",        none, pivots,    levels::construct_pivots);
+        self.register("fib_pivots",    none, pivots,    levels::construct_fibonacci_pivots);
+        self.register("camarilla",     none, camarilla, levels::construct_camarilla_pivots);
+        self.register("prev_day",      none, hlc,       levels::construct_previous_day);
+        self.register("prev_week",     none, hlc,       levels::construct_previous_week);
+        self.register("asian_range",   none, range,     levels::construct_asian_range);
+        self.register("london_range",  none, range,     levels::construct_london_range);
+        self.register("new_york_range", none, range,    levels::construct_new_york_range);
+        self.register("vwap",          none, single,    levels::construct_vwap);
+        self.register("vwap_asian",    none, single,    levels::construct_asian_vwap);
+        self.register("vwap_london",   none, single,    levels::construct_london_vwap);
+        self.register("vwap_new_york", none, single,    levels::construct_new_york_vwap);
+    }
+
+    // registering a name again replaces the earlier indicator
+    pub fn register(&mut self,
+                    name: &str,
+                    parameters: &[ParameterKind],
+                    outputs: &[&'static str],
+                    constructor: IndicatorConstructor) {
+        self.register_with_options(name, parameters, outputs, &[], constructor);
+    }
+
+    pub fn register_with_options(&mut self,
+                                 name: &str,
+                                 parameters: &[ParameterKind],
+                                 outputs: &[&'static str],
+                                 options: &[&'static str],
+                                 constructor: IndicatorConstructor) {
+        if 0 == outputs.len() {
+            panic!("indicator {} must have at least one output", name);
+        }
+
+        let indicator = RegisteredIndicator {
+            parameters: parameters.to_vec(),
+            outputs: outputs.to_vec(),
+            options: options.to_vec(),
+            constructor: constructor,
+            check: None,
+        };
+
+        self.indicators.insert(name.to_string(), indicator);
+    }
+
+    // `check` runs before the indicator is constructed from a definition with bound variables
+    pub fn set_check(&mut self, name: &str, check: ParameterCheck) {
+        match self.indicators.get_mut(name) {
+            Some(indicator) => indicator.check = Some(check),
+            None            => panic!("can't add a check to unknown indicator {}", name),
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&RegisteredIndicator> {
+        self.indicators.get(name)
+    }
+
+    // The errors IndicatorDefinition::param(), period() and the constructor would panic with,
+    // for definitions whose parameters were sampled from variables.
+    pub fn check(&self, definition: &IndicatorDefinition) -> Result<(), String> {
+        let indicator = match self.lookup(definition.indicator_type.as_slice()) {
+            Some(indicator) => indicator,
+            None            => {
+                return Err(format!("unknown indicator type: {}", definition.indicator_type))
+            },
+        };
+
+        for (index, &kind) in indicator.parameters.iter().enumerate() {
+            let param = definition.params[index];
+
+            if param <= 0.0 {
+                return Err(format!("{}: parameters must be > 0, got {}", definition.name, param));
+            }
+
+            if Period == kind && param != param.floor() {
+                return Err(format!(
+                    "{}: periods must be whole numbers, got {}",
+                    definition.name,
+                    param
+                ));
+            }
+        }
+
+        match indicator.check {
+            Some(check) => check(definition),
+            None        => Ok(()),
+        }
+    }
+
+    pub fn create(&self, definition: &IndicatorDefinition) -> Box<Indicator> {
+        match self.lookup(definition.indicator_type.as_slice()) {
+            Some(indicator) => (indicator.constructor)(definition),
+            None            => panic!("unknown indicator type: {}", definition.indicator_type),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::num::Float;
+
+use chart::{Candle, PriceSide};
+use indicators::{Indicator, IndicatorDefinition};
+use indicators::indicator_utils;
+use indicators::indicator_utils::Smoother;
+
+// ===== ADX =======================================================================================
+
+#[derive(Clone)]
+pub struct AverageDirectionalIndex {
+    period: uint,
+    side: PriceSide,
+    primed: bool,
+    range: Smoother,
+    plus_dm: Smoother,
+    minus_dm: Smoother,
+    adx: Smoother,
+}
+
+impl AverageDirectionalIndex {
+    pub fn new(period: uint, side: PriceSide) -> AverageDirectionalIndex {
+        AverageDirectionalIndex {
+            period: period,
+            side: side,
+            primed: false,
+            range: Smoother::wilder(period),
+            plus_dm: Smoother::wilder(period),
+            minus_dm: Smoother::wilder(period),
+            adx: Smoother::wilder(period),
+        }
+    }
+
+    fn directional_indexes(&self) -> (f32, f32) {
+        let range = self.range.value;
+
+        if 0.0 == range {
+            return (0.0, 0.0);
+        }
+
+        (100.0 * self.plus_dm.value / range, 100.0 * self.minus_dm.value / range)
+    }
+
+    fn feed(&mut self, candles: &Vec<Candle>, index: uint) {
+        let side = self.side;
+
+        let candle   = &candles[index];
+        let previous = &candles[index + 1];
+
+        let up   = candle.high(side) - previous.high(side);
+        let down = previous.low(side) - candle.low(side);
+
+        let plus_dm  = match up > down && up > 0.0 {
+            true  => up,
+            false => 0.0,
+        };
+        let minus_dm = match down > up && down > 0.0 {
+            true  => down,
+            false => 0.0,
+        };
+
+        self.range.push(indicator_utils::true_range(candles, side, index));
+        self.plus_dm.push(plus_dm);
+        self.minus_dm.push(minus_dm);
+
+        // ADX is the Wilder average of DX, which only exists once the DIs are seeded
+        if self.range.is_seeded() {
+            let (plus_di, minus_di) = self.directional_indexes();
+            let total = plus_di + minus_di;
+
+            let dx = match 0.0 == total {
+                true  => 0.0,
+                false => 100.0 * (plus_di - minus_di).abs() / total,
+            };
+
+            self.adx.push(dx);
+        }
+    }
+}
+
+impl Indicator for AverageDirectionalIndex {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            if i + 1 < candles.len() {
+                self.feed(candles, i);
+            }
+        }
+
+        self.primed = true;
+    }
+
+    // adx, plus_di, minus_di
+    fn outputs(&self) -> Vec<f32> {
+        let (plus_di, minus_di) = self.directional_indexes();
+
+        vec!(self.adx.value, plus_di, minus_di)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period * 2
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_adx(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let adx = AverageDirectionalIndex::new(definition.period(0), definition.source.side);
+    Box::new(adx) as Box<Indicator>
+}
+
+// ===== PARABOLIC SAR =============================================================================
+
+#[derive(Clone)]
+struct ParabolicState {
+    rising: bool,
+    sar: f32,
+    extreme: f32,
+    acceleration: f32,
+}
+
+#[derive(Clone)]
+pub struct ParabolicSAR {
+    step: f32,
+    maximum: f32,
+    side: PriceSide,
+    primed: bool,
+    state: Option<ParabolicState>,
+}
+
+impl ParabolicSAR {
+    pub fn new(step: f32, maximum: f32, side: PriceSide) -> ParabolicSAR {
+        if step > maximum {
+            panic!("parabolic SAR step ({}) must be <= maximum ({})", step, maximum);
+        }
+
+        ParabolicSAR { step: step, maximum: maximum, side: side, primed: false, state: None }
+    }
+
+    fn feed(&mut self, candles: &Vec<Candle>, index: uint) {
+        let side    = self.side;
+        let step    = self.step;
+        let maximum = self.maximum;
+
+        let candle   = candles[index];
+        let previous = candles[index + 1];
+
+        let mut state = match self.state.take() {
+            Some(state) => state,
+            None        => {
+                // start in the direction of the first close-to-close move
+                let rising = candle.close(side) > previous.close(side);
+
+                self.state = Some(ParabolicState {
+                    rising: rising,
+                    sar: match rising { true => previous.low(side), false => previous.high(side) },
+                    extreme: match rising { true => candle.high(side), false => candle.low(side) },
+                    acceleration: step,
+                });
+
+                return;
+            },
+        };
+
+        state.sar += state.acceleration * (state.extreme - state.sar);
+
+        // the SAR may not move into the range of the previous two candles
+        let mut limit_low  = previous.low(side);
+        let mut limit_high = previous.high(side);
+
+        if index + 2 < candles.len() {
+            limit_low  = limit_low.min(candles[index + 2].low(side));
+            limit_high = limit_high.max(candles[index + 2].high(side));
+        }
+
+        if state.rising {
+            state.sar = state.sar.min(limit_low);
+
+            if candle.low(side) < state.sar {
+                state.rising = false;
+                state.sar = state.extreme;
+                state.extreme = candle.low(side);
+                state.acceleration = step;
+            } else if candle.high(side) > state.extreme {
+                state.extreme = candle.high(side);
+                state.acceleration = (state.acceleration + step).min(maximum);
+            }
+        } else {
+            state.sar = state.sar.max(limit_high);
+
+            if candle.high(side) > state.sar {
+                state.rising = true;
+                state.sar = state.extreme;
+                state.extreme = candle.high(side);
+                state.acceleration = step;
+            } else if candle.low(side) < state.extreme {
+                state.extreme = candle.low(side);
+                state.acceleration = (state.acceleration + step).min(maximum);
+            }
+        }
+
+        self.state = Some(state);
+    }
+}
+
+impl Indicator for ParabolicSAR {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            if i + 1 < candles.len() {
+                self.feed(candles, i);
+            }
+        }
+
+        self.primed = true;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        match self.state {
+            Some(ref state) => vec!(state.sar),
+            None            => vec!(0.0),
+        }
+    }
+
+    fn warmup(&self) -> uint {
+        2
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_parabolic_sar(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let sar = ParabolicSAR::new(definition.param(0), definition.param(1), definition.source.side);
+    Box::new(sar) as Box<Indicator>
+}
+
+pub fn check_parabolic_sar(definition: &IndicatorDefinition) -> Result<(), String> {
+    let (step, maximum) = (definition.params[0], definition.params[1]);
+
+    match step <= maximum {
+        true  => Ok(()),
+        false => Err(format!(
+            "{}: parabolic SAR step ({}) must be <= maximum ({})",
+            definition.name,
+            step,
+            maximum
+        )),
+    }
+}
+
+// ===== TESTS =====================================================================================
+
+// Expected values come from a separate double precision implementation of Wilder's rules written
+// from his book's formulas (running sums for TR and DM, the first ADX a plain mean of 14 DX), not
+// from this code
+#[cfg(test)]
+mod tests {
+    use chart::PriceSide;
+    use indicators::indicator_utils::{assert_close, run_over_bars, REFERENCE_BARS};
+
+    use super::{AverageDirectionalIndex, ParabolicSAR};
+
+    #[test]
+    fn adx_matches_wilder() {
+        let mut adx = AverageDirectionalIndex::new(14, PriceSide::Bid);
+        let results = run_over_bars(&mut adx, &REFERENCE_BARS);
+
+        // 14 DX values are needed to seed the ADX, and the first DX needs 15 bars
+        assert!(results[26].is_none());
+
+        // bar, adx, +DI, -DI
+        let expected = [
+            (28u, 18.9859f32, 21.8966f32, 15.6753f32),
+            (32,  21.1008,    26.1336,    11.4878),
+            (36,  26.9144,    25.3769,     8.9510),
+            (40,  32.4620,    22.8823,     8.7231),
+        ];
+
+        for &(bar, adx, plus_di, minus_di) in expected.iter() {
+            let outputs = results[bar - 1].clone().unwrap();
+
+            assert_close(outputs[0], adx, 0.01, format!("ADX at bar {}", bar).as_slice());
+            assert_close(outputs[1], plus_di, 0.01, format!("+DI at bar {}", bar).as_slice());
+            assert_close(outputs[2], minus_di, 0.01, format!("-DI at bar {}", bar).as_slice());
+        }
+    }
+
+    #[test]
+    fn parabolic_sar_matches_wilder() {
+        let mut sar = ParabolicSAR::new(0.02, 0.2, PriceSide::Bid);
+        let results = run_over_bars(&mut sar, &REFERENCE_BARS);
+
+        // bars 2 to 40, starting long because bar 2 closed higher than bar 1.  By hand: the SAR
+        // starts at bar 1's low (49.62), stays there at bar 3 because it may not rise above the
+        // lows of bars 1 and 2, then moves 0.04 * (52.32 - 49.62) towards bar 3's high at bar 4
+        let expected = [
+            49.6200f32, 49.6200, 49.7280, 49.8317, 50.0026, 50.2440, 50.6356, 50.9880, 51.3927,
+            51.8753, 52.3784, 52.8827, 55.1800, 55.1380, 55.0373, 54.8154, 54.5254, 54.0879,
+            53.6941, 53.2244, 52.5882, 51.9101, 51.3405, 50.7230, 50.2166, 47.9100, 47.9658,
+            48.1328, 48.2931, 48.5443, 48.8975, 49.4238, 49.8974, 50.3237, 50.8944, 51.3967,
+            51.9768, 52.5757, 53.1561,
+        ];
+
+        assert!(results[0].is_none());
+
+        for (index, &value) in expected.iter().enumerate() {
+            let bar = index + 2;
+            let outputs = results[bar - 1].clone().unwrap();
+            assert_close(outputs[0], value, 0.001, format!("SAR at bar {}", bar).as_slice());
+        }
+    }
+
+    #[test]
+    fn parabolic_sar_reverses() {
+        let mut sar = ParabolicSAR::new(0.02, 0.2, PriceSide::Bid);
+        let results = run_over_bars(&mut sar, &REFERENCE_BARS);
+
+        // below the lows until the sell-off at bar 14, above the highs until bar 27, then below
+        for bar in range(2u, 41) {
+            let value = results[bar - 1].clone().unwrap()[0];
+            let (_, high, low, _) = REFERENCE_BARS[bar - 1];
+
+            match bar {
+                14...26 => assert!(value > high, "SAR {} not above bar {}", value, bar),
+                _       => assert!(value < low, "SAR {} not below bar {}", value, bar),
+            }
+        }
+
+        // each reversal starts from the extreme of the trend before it
+        assert_close(results[13].clone().unwrap()[0], 55.18, 0.001, "SAR at bar 14");
+        assert_close(results[26].clone().unwrap()[0], 47.91, 0.001, "SAR at bar 27");
+    }
+}
@@ -0,0 +1,278 @@
+use std::cmp;
+use std::num::Float;
+
+use chart::{Candle, PriceSide, PriceSource};
+use indicators::{Indicator, IndicatorDefinition};
+use indicators::indicator_utils;
+use indicators::indicator_utils::Smoother;
+
+// ===== ATR =======================================================================================
+
+// Wilder: the first value is the mean of the first `period` true ranges (the oldest candle has no
+// previous close, so its true range is high - low), then each new range is smoothed in by 1/period
+#[derive(Clone)]
+pub struct AverageTrueRange {
+    period: uint,
+    side: PriceSide,
+    primed: bool,
+    average: Smoother,
+}
+
+impl AverageTrueRange {
+    pub fn new(period: uint, side: PriceSide) -> AverageTrueRange {
+        AverageTrueRange {
+            period: period,
+            side: side,
+            primed: false,
+            average: Smoother::wilder(period),
+        }
+    }
+}
+
+impl Indicator for AverageTrueRange {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            self.average.push(indicator_utils::true_range(candles, self.side, i));
+        }
+
+        self.primed = true;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.average.value)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_atr(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let atr = AverageTrueRange::new(definition.period(0), definition.source.side);
+    Box::new(atr) as Box<Indicator>
+}
+
+// ===== BOLLINGER BANDS ===========================================================================
+
+#[derive(Clone)]
+pub struct BollingerBands {
+    period: uint,
+    deviations: f32,
+    source: PriceSource,
+    upper: f32,
+    middle: f32,
+    lower: f32,
+}
+
+impl BollingerBands {
+    pub fn new(period: uint, deviations: f32, source: PriceSource) -> BollingerBands {
+        BollingerBands {
+            period: period,
+            deviations: deviations,
+            source: source,
+            upper: 0.0,
+            middle: 0.0,
+            lower: 0.0,
+        }
+    }
+}
+
+impl Indicator for BollingerBands {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        let middle = indicator_utils::mean_price(candles, self.source, 1, self.period);
+
+        let mut variance = 0.0f32;
+
+        for i in range(1, self.period + 1) {
+            variance += (self.source.value(&candles[i]) - middle).powi(2);
+        }
+
+        let width = self.deviations * (variance / self.period as f32).sqrt();
+
+        self.upper  = middle + width;
+        self.middle = middle;
+        self.lower  = middle - width;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.upper, self.middle, self.lower)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_bollinger(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let bands = BollingerBands::new(definition.period(0), definition.param(1), definition.source);
+    Box::new(bands) as Box<Indicator>
+}
+
+// ===== KELTNER CHANNELS ==========================================================================
+
+// EMA of the price plus and minus a multiple of the (Wilder) ATR
+#[derive(Clone)]
+pub struct KeltnerChannels {
+    ema_period: uint,
+    atr_period: uint,
+    multiplier: f32,
+    source: PriceSource,
+    primed: bool,
+    average: Smoother,
+    range: Smoother,
+}
+
+impl KeltnerChannels {
+    pub fn new(ema_period: uint,
+               atr_period: uint,
+               multiplier: f32,
+               source: PriceSource) -> KeltnerChannels {
+        KeltnerChannels {
+            ema_period: ema_period,
+            atr_period: atr_period,
+            multiplier: multiplier,
+            source: source,
+            primed: false,
+            average: Smoother::ema(ema_period),
+            range: Smoother::wilder(atr_period),
+        }
+    }
+}
+
+impl Indicator for KeltnerChannels {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        for &i in indicator_utils::new_candle_indexes(self.primed, candles).iter() {
+            self.average.push(self.source.value(&candles[i]));
+            self.range.push(indicator_utils::true_range(candles, self.source.side, i));
+        }
+
+        self.primed = true;
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        let middle = self.average.value;
+        let width  = self.multiplier * self.range.value;
+
+        vec!(middle + width, middle, middle - width)
+    }
+
+    fn warmup(&self) -> uint {
+        cmp::max(self.ema_period, self.atr_period)
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_keltner(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let channels = KeltnerChannels::new(
+        definition.period(0),
+        definition.period(1),
+        definition.param(2),
+        definition.source,
+    );
+
+    Box::new(channels) as Box<Indicator>
+}
+
+// ===== DONCHIAN CHANNELS =========================================================================
+
+#[derive(Clone)]
+pub struct DonchianChannels {
+    period: uint,
+    side: PriceSide,
+    upper: f32,
+    lower: f32,
+}
+
+impl DonchianChannels {
+    pub fn new(period: uint, side: PriceSide) -> DonchianChannels {
+        DonchianChannels { period: period, side: side, upper: 0.0, lower: 0.0 }
+    }
+}
+
+impl Indicator for DonchianChannels {
+    fn update(&mut self, candles: &Vec<Candle>) {
+        self.upper = indicator_utils::highest_high(candles, self.side, 1, self.period);
+        self.lower = indicator_utils::lowest_low(candles, self.side, 1, self.period);
+    }
+
+    fn outputs(&self) -> Vec<f32> {
+        vec!(self.upper, (self.upper + self.lower) / 2.0, self.lower)
+    }
+
+    fn warmup(&self) -> uint {
+        self.period
+    }
+
+    fn box_clone(&self) -> Box<Indicator> {
+        Box::new(self.clone()) as Box<Indicator>
+    }
+}
+
+pub fn construct_donchian(definition: &IndicatorDefinition) -> Box<Indicator> {
+    let channels = DonchianChannels::new(definition.period(0), definition.source.side);
+    Box::new(channels) as Box<Indicator>
+}
+
+// ===== TESTS =====================================================================================
+
+// Expected values come from a separate double precision implementation of Wilder's rules written
+// from his book's formulas (running sums, TR14 = TR14 - TR14 / 14 + TR), not from this code
+#[cfg(test)]
+mod tests {
+    use chart::PriceSide;
+    use indicators::indicator_utils::{assert_close, run_over_bars, REFERENCE_BARS};
+
+    use super::AverageTrueRange;
+
+    #[test]
+    fn atr_matches_wilder() {
+        let mut atr = AverageTrueRange::new(14, PriceSide::Bid);
+        let results = run_over_bars(&mut atr, &REFERENCE_BARS);
+
+        assert!(results[12].is_none());
+
+        // the seed can be checked by hand: the true ranges of bars 1 to 14 are 1.42, 1.07, 1.77,
+        // 1.05, 1.60, 1.10, 1.95, 0.95, 1.27, 1.22, 0.90, 1.40, 0.72 and 1.92, which sum to 18.34
+        assert_close(results[13].clone().unwrap()[0], 18.34 / 14.0, 0.0001, "ATR at bar 14");
+
+        let expected = [(15u, 1.3179f32), (21, 1.4442), (28, 1.4768), (34, 1.4460), (40, 1.3709)];
+
+        for &(bar, value) in expected.iter() {
+            let outputs = results[bar - 1].clone().unwrap();
+            assert_close(outputs[0], value, 0.001, format!("ATR at bar {}", bar).as_slice());
+        }
+    }
+
+    #[test]
+    fn atr_short_period_every_bar() {
+        let mut atr = AverageTrueRange::new(5, PriceSide::Bid);
+        let results = run_over_bars(&mut atr, &REFERENCE_BARS);
+
+        // bars 5 to 40, before that it's warming up
+        let expected = [
+            1.3820f32, 1.3256, 1.4505, 1.3504, 1.3343, 1.3114, 1.2292, 1.2633, 1.1547, 1.3077,
+            1.3302, 1.5241, 1.4333, 1.5207, 1.4265, 1.4612, 1.5930, 1.5244, 1.3795, 1.3896,
+            1.3277, 1.4361, 1.4389, 1.6311, 1.5289, 1.5631, 1.5045, 1.6136, 1.5209, 1.4367,
+            1.5094, 1.3815, 1.4092, 1.3074, 1.3159, 1.2687,
+        ];
+
+        assert!(results[3].is_none());
+
+        for (index, &value) in expected.iter().enumerate() {
+            let bar = index + 5;
+            let outputs = results[bar - 1].clone().unwrap();
+            assert_close(outputs[0], value, 0.001, format!("ATR(5) at bar {}", bar).as_slice());
+        }
+    }
+}
@@ -11,14 +11,19 @@ pub use trade::Trade;
 pub mod algorithm;
 pub mod chart;
 pub mod config;
+pub mod config_tables;
+pub mod cross_indicators;
 pub mod indicators;
 pub mod lua;
 pub mod optimizer;
+pub mod order;
 pub mod parsers;
 pub mod parser_utils;
 pub mod range_bound_variable;
+pub mod sessions;
 pub mod simulation;
 pub mod strategy;
 pub mod tick;
+pub mod tick_statistics;
 pub mod trade;
 pub mod utilities;
@@ -1,182 +1,898 @@
 extern crate libc;
+extern crate time;
 
 use self::libc::{c_char,c_int,c_float};
 // use std::c_str::ToCStr;
 use std::ffi::CString;
 use std::ffi;
+use std::ptr;
 use std::str;
 
+use order::{Order, OrderAction};
+use range_bound_variable::RangeBoundVariables;
+use sessions;
+use sessions::Session;
+use simulation::Simulation;
+use tick::Tick;
+use trade::Trade;
+use utilities;
+
+// opaque C lua_State
+enum LuaState {}
+
 #[link(name = "bridge")]
 extern {
     // interpreter functions
-    fn lua_bridge_setup(path: *const libc::c_char);
-    fn lua_bridge_teardown();
+    fn lua_bridge_setup() -> *mut LuaState;
+    fn lua_bridge_load(state: *mut LuaState, path: *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_teardown(state: *mut LuaState);
+    fn lua_bridge_seed_random(state: *mut LuaState, seed: libc::c_uint, stream: libc::c_uint);
+    fn lua_bridge_sandbox(state: *mut LuaState,
+                          unsafe_libs: libc::c_int,
+                          instruction_limit: libc::c_int,
+                          memory_limit: libc::c_int);
+
+    // error functions
+    fn lua_bridge_error_message(state: *mut LuaState) -> *const libc::c_char;
+    fn lua_bridge_error_traceback(state: *mut LuaState) -> *const libc::c_char;
 
     // config file functions
-    // fn lua_bridge_open_config(path: *const libc::c_char);
+    // fn lua_bridge_open_config(state: *mut LuaState, path: *const libc::c_char) -> libc::c_int;
 
     // variable functions
-    fn lua_bridge_get_string_var(name: *const libc::c_char) -> *const libc::c_char;
-    fn lua_bridge_get_int_var(name: *const libc::c_char) -> libc::c_int;
-    fn lua_bridge_register_string(name: *const libc::c_char, value: *const libc::c_char);
-    fn lua_bridge_register_number(name: *const libc::c_char, value: libc::c_float);
-    fn lua_bridge_register_boolean(name: *const libc::c_char, value: libc::c_int);
+    fn lua_bridge_get_string_var(state: *mut LuaState,
+                                 name: *const libc::c_char,
+                                 value: *mut *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_get_optional_string_var(state: *mut LuaState,
+                                          name: *const libc::c_char,
+                                          value: *mut *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_get_int_var(state: *mut LuaState,
+                              name: *const libc::c_char,
+                              value: *mut libc::c_int) -> libc::c_int;
+    fn lua_bridge_get_optional_int_var(state: *mut LuaState,
+                                       name: *const libc::c_char,
+                                       fallback: libc::c_int,
+                                       value: *mut libc::c_int) -> libc::c_int;
+    fn lua_bridge_get_optional_bool_var(state: *mut LuaState,
+                                        name: *const libc::c_char,
+                                        fallback: libc::c_int,
+                                        value: *mut libc::c_int) -> libc::c_int;
+    fn lua_bridge_register_string(state: *mut LuaState,
+                                  name: *const libc::c_char,
+                                  value: *const libc::c_char);
+    fn lua_bridge_register_number(state: *mut LuaState,
+                                  name: *const libc::c_char,
+                                  value: libc::c_float);
+    fn lua_bridge_register_integer(state: *mut LuaState,
+                                   name: *const libc::c_char,
+                                   value: libc::c_int);
+    fn lua_bridge_register_boolean(state: *mut LuaState,
+                                   name: *const libc::c_char,
+                                   value: libc::c_int);
+
+    // callback functions
+    fn lua_bridge_has_function(state: *mut LuaState, name: *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_call(state: *mut LuaState,
+                       name: *const libc::c_char,
+                       nargs: libc::c_int) -> libc::c_int;
 
     // trading functions
-    fn lua_get_decision() -> libc::c_int; // TODO: rename so it matches the rest
-    fn lua_bridge_on_tick();
+    fn lua_bridge_clear_orders(state: *mut LuaState);
+    fn lua_bridge_order_count(state: *mut LuaState) -> libc::c_int;
+    fn lua_bridge_get_order(state: *mut LuaState, index: libc::c_int, order: *mut COrder);
+
+    // output functions
+    fn lua_bridge_clear_output(state: *mut LuaState);
+    fn lua_bridge_log_count(state: *mut LuaState) -> libc::c_int;
+    fn lua_bridge_get_log(state: *mut LuaState, index: libc::c_int, message: *mut CLogMessage);
+    fn lua_bridge_plot_count(state: *mut LuaState) -> libc::c_int;
+    fn lua_bridge_get_plot(state: *mut LuaState, index: libc::c_int, point: *mut CPlotPoint);
+
+    // tick functions
+    fn lua_bridge_set_tick(state: *mut LuaState, tick: *const CTick, context: *const CContext);
+    fn lua_bridge_push_tick_arguments(state: *mut LuaState);
+
+    // account functions
+    fn lua_bridge_clear_positions(state: *mut LuaState);
+    fn lua_bridge_push_position(state: *mut LuaState, position: *const CPosition);
+    fn lua_bridge_set_position_profits(state: *mut LuaState,
+                                       profits: *const libc::c_float,
+                                       count: libc::c_int);
+
+    // config functions
+    fn lua_bridge_push_global(state: *mut LuaState, name: *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_push_field(state: *mut LuaState, name: *const libc::c_char) -> libc::c_int;
+    fn lua_bridge_push_index(state: *mut LuaState, index: libc::c_int) -> libc::c_int;
+    fn lua_bridge_pop(state: *mut LuaState);
+    fn lua_bridge_length(state: *mut LuaState) -> libc::c_int;
+    fn lua_bridge_number(state: *mut LuaState) -> libc::c_double;
+    fn lua_bridge_string(state: *mut LuaState) -> *const libc::c_char;
+    fn lua_bridge_boolean(state: *mut LuaState) -> libc::c_int;
+    fn lua_bridge_string_key(state: *mut LuaState, n: libc::c_int) -> *const libc::c_char;
 
     // chart functions
-    fn lua_bridge_create_table(size: libc::c_int);
-    fn lua_bridge_push_table_integer(num: libc::c_int);
-    fn lua_bridge_push_table_number(num: libc::c_float);
-    fn lua_bridge_push_table_string(name: *const libc::c_char);
-    fn lua_bridge_set_table(offset: libc::c_int);
-    fn lua_bridge_finalize_table(name: *const libc::c_char);
+    fn lua_bridge_create_table(state: *mut LuaState, size: libc::c_int);
+    fn lua_bridge_push_table_integer(state: *mut LuaState, num: libc::c_int);
+    fn lua_bridge_push_table_number(state: *mut LuaState, num: libc::c_float);
+    fn lua_bridge_push_table_string(state: *mut LuaState, name: *const libc::c_char);
+    fn lua_bridge_push_table_boolean(state: *mut LuaState, value: libc::c_int);
+    fn lua_bridge_set_table(state: *mut LuaState, offset: libc::c_int);
+    fn lua_bridge_finalize_table(state: *mut LuaState, name: *const libc::c_char);
+
+    // indicator functions
+    fn lua_bridge_call_indicator(state: *mut LuaState,
+                                 function: *const libc::c_char,
+                                 chart: *const libc::c_char,
+                                 indicator: *const libc::c_char,
+                                 value: *mut libc::c_float) -> libc::c_int;
 }
 
-// ===== INTERPRETER FUNCTIONS =====================================================================
+// copies a string the interpreter owns
+unsafe fn to_string(c_ptr: *const libc::c_char) -> String {
+    let slice = ffi::c_str_to_bytes(&c_ptr);
+    str::from_utf8(slice).unwrap().to_string()
+}
 
-pub fn setup(path: &str) {
-    println!("Starting Lua interpreter with script {}", path);
+// struct order in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct COrder {
+    action: libc::c_int,
+    id: libc::c_int,
+    size: libc::c_float,
+    has_stop_loss: libc::c_int,
+    stop_loss: libc::c_float,
+    has_take_profit: libc::c_int,
+    take_profit: libc::c_float,
+    magic: libc::c_int,
+    comment: *const libc::c_char,
+}
 
-    unsafe {
-        lua_bridge_setup(path.to_c_str().as_ptr());
-    }
+// struct tick_argument in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct CTick {
+    time: libc::c_double,
+    bid: libc::c_double,
+    ask: libc::c_double,
+    hour: libc::c_int,
+    minute: libc::c_int,
+    weekday: libc::c_int,
+    day: libc::c_int,
+    asian_open: libc::c_int,
+    sydney_open: libc::c_int,
+    tokyo_open: libc::c_int,
+    london_open: libc::c_int,
+    new_york_open: libc::c_int,
 }
 
-pub fn teardown() {
-    println!("Stopping Lua interpeter");
+// struct context in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct CContext {
+    balance: libc::c_double,
+    equity: libc::c_double,
+    drawdown: libc::c_double,
+    free_margin: libc::c_double,
+    open_trades: libc::c_int,
+    tick_velocity: libc::c_double,
+    spread_average: libc::c_double,
+    spread_max: libc::c_double,
+    realized_volatility: libc::c_double,
+    seconds_since_last_tick: libc::c_double,
+}
 
-    unsafe {
-        lua_bridge_teardown();
-    }
+// struct position in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct CPosition {
+    id: libc::c_int,
+    is_long: libc::c_int,
+    size: libc::c_float,
+    open_price: libc::c_float,
+    open_time: libc::c_double,
+    profit: libc::c_float,
+    has_stop_loss: libc::c_int,
+    stop_loss: libc::c_float,
+    has_take_profit: libc::c_int,
+    take_profit: libc::c_float,
+    magic: libc::c_int,
+    comment: *const libc::c_char,
 }
 
-// ===== CHART FUNCTIONS ===========================================================================
+// struct log_message in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct CLogMessage {
+    level: *const libc::c_char,
+    message: *const libc::c_char,
+}
 
-pub fn create_table(size: i32) {
-    // println!("new lua table with size: {}", size);
-    unsafe {
-        lua_bridge_create_table(size);
-    }
+// struct plot_point in bridge.c
+#[repr(C)]
+#[derive(Copy)]
+struct CPlotPoint {
+    series: *const libc::c_char,
+    value: libc::c_double,
+    chart: *const libc::c_char,
 }
 
-pub fn push_table_integer(num: i32) {
-    // println!("pushed integer: {}", num);
-    unsafe {
-        lua_bridge_push_table_integer(num);
+fn to_c_bool(value: bool) -> libc::c_int {
+    match value {
+        true  => 1,
+        false => 0,
     }
 }
 
-pub fn push_table_number(num: f32) {
-    // println!("pushed number: {}", num);
-    unsafe {
-        lua_bridge_push_table_number(num);
-    }
+// the type of a config value, see the CONFIG FUNCTIONS
+#[derive(Clone,Copy,PartialEq,Show)]
+pub enum LuaType {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    Table,
+    Other, // functions, userdata, ...
 }
 
-pub fn push_table_string(s: &str) {
-    // println!("pushed string: {}", s);
-    unsafe {
-        lua_bridge_push_table_string(s.to_c_str().as_ptr());
+impl LuaType {
+    // LUA_TNIL, LUA_TBOOLEAN, ... in lua.h
+    fn from_int(t: libc::c_int) -> LuaType {
+        match t {
+            0 => LuaType::Nil,
+            1 => LuaType::Boolean,
+            3 => LuaType::Number,
+            4 => LuaType::String,
+            5 => LuaType::Table,
+            _ => LuaType::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LuaType::Nil     => "nil",
+            LuaType::Boolean => "a boolean",
+            LuaType::Number  => "a number",
+            LuaType::String  => "a string",
+            LuaType::Table   => "a table",
+            LuaType::Other   => "a function or userdata",
+        }
     }
 }
 
-pub fn set_table(offset: i32) {
-    // println!("pushed table with offset: {}", offset);
-    unsafe {
-        lua_bridge_set_table(offset);
+// What a strategy script may do, see lua_bridge_sandbox() in bridge.c.  The limits are off at 0.
+#[derive(Clone,Copy,Show)]
+pub struct Sandbox {
+    pub unsafe_libs: bool,      // io, os.execute(), require(), debug, ...
+    pub instruction_limit: i32, // per call into the script
+    pub memory_limit: i32,      // MB
+}
+
+// the strategy's log(level, message)
+#[derive(Clone,Show)]
+pub struct LogMessage {
+    pub level: String,
+    pub message: String,
+}
+
+// the strategy's plot(series, value[, chart])
+#[derive(Clone,Show)]
+pub struct PlotPoint {
+    pub series: String,
+    pub value: f32,
+    pub chart: Option<String>,
+}
+
+// An error raised by the strategy script, or by the bridge over something the script did, e.g.
+// returning a string from an indicator function.  The simulation fills in when it happened.
+#[derive(Clone)]
+pub struct LuaError {
+    pub message: String,
+    pub traceback: String,
+
+    // time of the tick being processed, None if the script failed while loading
+    pub tick_time: Option<time::Tm>,
+
+    // the variable set the simulation was running with
+    pub variables: Option<RangeBoundVariables>,
+}
+
+impl LuaError {
+    // for errors found outside the interpreter, e.g. closing a trade that doesn't exist
+    pub fn new(message: String) -> LuaError {
+        LuaError {
+            message: message,
+            traceback: String::new(),
+            tick_time: None,
+            variables: None,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("Lua error: {}", self.message);
+
+        match self.tick_time {
+            Some(tick_time) => println!("  at tick {}", utilities::tm_to_iso(tick_time)),
+            None            => println!("  while loading the script"),
+        }
+
+        if !self.traceback.is_empty() {
+            println!("{}", self.traceback);
+        }
+
+        match self.variables {
+            Some(ref variables) => {
+                println!("Variables:");
+                variables.print();
+            },
+            None => {},
+        }
     }
 }
 
-pub fn finalize_table(name: &str) {
-    // println!("finalizing table with name: {}", name);
-    unsafe {
-        lua_bridge_finalize_table(name.to_c_str().as_ptr());
+// An interpreter with a script loaded, closed when it's dropped.  Each simulation gets its own,
+// and whatever publishes to or calls into the script is handed the one it should use.
+pub struct Lua {
+    state: *mut LuaState,
+}
+
+// A state is only ever used by one thread at a time, whichever owns the handle.
+unsafe impl Send for Lua {}
+
+impl Drop for Lua {
+    fn drop(&mut self) {
+        println!("Stopping Lua interpeter");
+
+        unsafe {
+            lua_bridge_teardown(self.state);
+        }
     }
 }
 
-// ===== VARIABLE FUNCTIONS ========================================================================
+impl Lua {
+    // ===== INTERPRETER FUNCTIONS =================================================================
+
+    // Fails when the script doesn't load or raises an error while it runs for the first time.
+    pub fn new(path: &str) -> Result<Lua, LuaError> {
+        Lua::start(path).load(path)
+    }
+
+    // Like new(), for strategies.  math.random() is seeded from `seed` and `stream`, and the
+    // script runs in `sandbox`.
+    pub fn new_sandboxed(path: &str,
+                         sandbox: &Sandbox,
+                         seed: i32,
+                         stream: i32) -> Result<Lua, LuaError> {
+        let lua = Lua::start(path);
+
+        unsafe {
+            lua_bridge_seed_random(lua.state, seed as libc::c_uint, stream as libc::c_uint);
+
+            lua_bridge_sandbox(
+                lua.state,
+                to_c_bool(sandbox.unsafe_libs),
+                sandbox.instruction_limit,
+                sandbox.memory_limit,
+            );
+        }
+
+        lua.load(path)
+    }
+
+    fn start(path: &str) -> Lua {
+        println!("Starting Lua interpreter with script {}", path);
+
+        Lua {
+            state: unsafe { lua_bridge_setup() },
+        }
+    }
+
+    fn load(self, path: &str) -> Result<Lua, LuaError> {
+        match unsafe { lua_bridge_load(self.state, path.to_c_str().as_ptr()) } {
+            0 => Ok(self),
+            _ => Err(self.error()),
+        }
+    }
+
+    // the error the last failed call left behind
+    fn error(&self) -> LuaError {
+        unsafe {
+            LuaError {
+                message: to_string(lua_bridge_error_message(self.state)),
+                traceback: to_string(lua_bridge_error_traceback(self.state)),
+                tick_time: None,
+                variables: None,
+            }
+        }
+    }
+
+    // ===== CONFIG FUNCTIONS ======================================================================
+
+    // These walk nested tables on the stack.  Every push_*() needs a pop(), and the others read
+    // the value pushed last.
+
+    pub fn push_global(&self, name: &str) -> LuaType {
+        LuaType::from_int(unsafe { lua_bridge_push_global(self.state, name.to_c_str().as_ptr()) })
+    }
+
+    pub fn push_field(&self, name: &str) -> LuaType {
+        LuaType::from_int(unsafe { lua_bridge_push_field(self.state, name.to_c_str().as_ptr()) })
+    }
+
+    // `index` starts at 1, as in Lua
+    pub fn push_index(&self, index: i32) -> LuaType {
+        LuaType::from_int(unsafe { lua_bridge_push_index(self.state, index) })
+    }
+
+    pub fn pop(&self) {
+        unsafe {
+            lua_bridge_pop(self.state);
+        }
+    }
+
+    pub fn length(&self) -> i32 {
+        unsafe { lua_bridge_length(self.state) }
+    }
+
+    pub fn number(&self) -> f64 {
+        unsafe { lua_bridge_number(self.state) as f64 }
+    }
+
+    pub fn string(&self) -> String {
+        unsafe { to_string(lua_bridge_string(self.state)) }
+    }
+
+    pub fn boolean(&self) -> bool {
+        0 != unsafe { lua_bridge_boolean(self.state) }
+    }
+
+    // sorted, so they're read in the same order every run
+    pub fn string_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = vec!();
+
+        loop {
+            let key = unsafe { lua_bridge_string_key(self.state, keys.len() as i32) };
+
+            if key.is_null() {
+                break;
+            }
+
+            keys.push(unsafe { to_string(key) });
+        }
+
+        keys.sort();
+        keys
+    }
+
+    // ===== CHART FUNCTIONS =======================================================================
+
+    pub fn create_table(&self, size: i32) {
+        // println!("new lua table with size: {}", size);
+        unsafe {
+            lua_bridge_create_table(self.state, size);
+        }
+    }
+
+    pub fn push_table_integer(&self, num: i32) {
+        // println!("pushed integer: {}", num);
+        unsafe {
+            lua_bridge_push_table_integer(self.state, num);
+        }
+    }
+
+    pub fn push_table_number(&self, num: f32) {
+        // println!("pushed number: {}", num);
+        unsafe {
+            lua_bridge_push_table_number(self.state, num);
+        }
+    }
 
-    pub fn get_string_var(name: &str) -> String {
-        // *const libc::c_char
-        let s = unsafe {
-            let c_ptr = lua_bridge_get_string_var(name.to_c_str().as_ptr());
-            let slice = ffi::c_str_to_bytes(&c_ptr);
-            str::from_utf8(slice).unwrap()
-            // CString::new(c_ptr, false)  // false because you don't own this string, it is static
+    pub fn push_table_string(&self, s: &str) {
+        // println!("pushed string: {}", s);
+        unsafe {
+            lua_bridge_push_table_string(self.state, s.to_c_str().as_ptr());
+        }
+    }
+
+    pub fn push_table_boolean(&self, value: bool) {
+        unsafe {
+            lua_bridge_push_table_boolean(self.state, to_c_bool(value));
+        }
+    }
+
+    pub fn set_table(&self, offset: i32) {
+        // println!("pushed table with offset: {}", offset);
+        unsafe {
+            lua_bridge_set_table(self.state, offset);
+        }
+    }
+
+    pub fn finalize_table(&self, name: &str) {
+        // println!("finalizing table with name: {}", name);
+        unsafe {
+            lua_bridge_finalize_table(self.state, name.to_c_str().as_ptr());
+        }
+    }
+
+    // these set a field of the table on top of the stack
+
+    pub fn set_field_integer(&self, name: &str, value: i32) {
+        self.push_table_string(name);
+        self.push_table_integer(value);
+        self.set_table(-3);
+    }
+
+    pub fn set_field_number(&self, name: &str, value: f32) {
+        self.push_table_string(name);
+        self.push_table_number(value);
+        self.set_table(-3);
+    }
+
+    pub fn set_field_string(&self, name: &str, value: &str) {
+        self.push_table_string(name);
+        self.push_table_string(value);
+        self.set_table(-3);
+    }
+
+    pub fn set_field_boolean(&self, name: &str, value: bool) {
+        self.push_table_string(name);
+        self.push_table_boolean(value);
+        self.set_table(-3);
+    }
+
+    // ===== INDICATOR FUNCTIONS ===================================================================
+
+    // Calls the function registered under `function` with the chart's candle table and the
+    // state it returned last time for `indicator`.
+    pub fn call_indicator(&self,
+                          function: &str,
+                          chart: &str,
+                          indicator: &str) -> Result<f32, LuaError> {
+        let mut value = 0.0f32;
+
+        let status = unsafe {
+            lua_bridge_call_indicator(
+                self.state,
+                function.to_c_str().as_ptr(),
+                chart.to_c_str().as_ptr(),
+                indicator.to_c_str().as_ptr(),
+                &mut value,
+            )
         };
-    
-        let s_slice: &str = s.as_str().unwrap().clone();
-    
-        // s_slice.to_string()
+
+        match status {
+            0 => Ok(value),
+            _ => Err(self.error()),
+        }
     }
 
-pub fn get_int_var(name: &str) -> i32 {
-    unsafe {
-        lua_bridge_get_int_var(name.to_c_str().as_ptr())
+    // ===== VARIABLE FUNCTIONS ====================================================================
+
+    // These read the configuration, so a variable of the wrong type panics.
+
+    pub fn get_string_var(&self, name: &str) -> String {
+        let mut c_ptr: *const libc::c_char = ptr::null();
+
+        unsafe {
+            if 0 != lua_bridge_get_string_var(self.state, name.to_c_str().as_ptr(), &mut c_ptr) {
+                panic!("{}", self.error().message);
+            }
+
+            to_string(c_ptr)
+        }
     }
-}
 
-pub fn register_string(name: &str, value: &str) {
-    unsafe {
-        lua_bridge_register_string(name.to_c_str().as_ptr(), value.to_c_str().as_ptr());
+    pub fn get_int_var(&self, name: &str) -> i32 {
+        let mut value = 0i32;
+
+        unsafe {
+            if 0 != lua_bridge_get_int_var(self.state, name.to_c_str().as_ptr(), &mut value) {
+                panic!("{}", self.error().message);
+            }
+        }
+
+        value
     }
-}
 
-pub fn register_number(name: &str, value: f32) {
-    unsafe {
-        lua_bridge_register_number(name.to_c_str().as_ptr(), value);
+    // `fallback` is used when the script doesn't set the variable
+    pub fn get_optional_string_var(&self, name: &str, fallback: &str) -> String {
+        let mut c_ptr: *const libc::c_char = ptr::null();
+
+        unsafe {
+            let status = lua_bridge_get_optional_string_var(
+                self.state,
+                name.to_c_str().as_ptr(),
+                &mut c_ptr,
+            );
+
+            if 0 != status {
+                panic!("{}", self.error().message);
+            }
+
+            match c_ptr.is_null() {
+                true  => fallback.to_string(),
+                false => to_string(c_ptr),
+            }
+        }
     }
-}
 
-pub fn register_boolean(name: &str, value: bool) {
-    let int_form = match value {
-        true  => 1,
-        false => 0,
-    };
+    // `fallback` is used when the script doesn't set the variable
+    pub fn get_optional_int_var(&self, name: &str, fallback: i32) -> i32 {
+        let mut value = fallback;
+
+        unsafe {
+            let status = lua_bridge_get_optional_int_var(
+                self.state,
+                name.to_c_str().as_ptr(),
+                fallback,
+                &mut value,
+            );
 
-    unsafe {
-        lua_bridge_register_boolean(name.to_c_str().as_ptr(), int_form);
+            if 0 != status {
+                panic!("{}", self.error().message);
+            }
+        }
+
+        value
     }
-}
 
-// ===== TRADING FUNCTIONS =========================================================================
+    // `fallback` is used when the script doesn't set the variable
+    pub fn get_optional_bool_var(&self, name: &str, fallback: bool) -> bool {
+        let mut value = to_c_bool(fallback);
 
-#[derive(Copy)]
-pub enum TradeDecision {
-    NOOP,
-    LONG,
-    SHORT,
-    CLOSE,
-}
+        unsafe {
+            let status = lua_bridge_get_optional_bool_var(
+                self.state,
+                name.to_c_str().as_ptr(),
+                to_c_bool(fallback),
+                &mut value,
+            );
+
+            if 0 != status {
+                panic!("{}", self.error().message);
+            }
+        }
 
-pub fn on_tick() -> TradeDecision {
-    unsafe {
-        lua_bridge_on_tick();
+        0 != value
     }
 
-    // enum DECISION {
-    //     NOOP = 0,
-    //     LONG = 1,
-    //     SHORT = 2,
-    //     CLOSE = 3
-    // } trading_decisions;
+    pub fn register_string(&self, name: &str, value: &str) {
+        unsafe {
+            lua_bridge_register_string(
+                self.state,
+                name.to_c_str().as_ptr(),
+                value.to_c_str().as_ptr(),
+            );
+        }
+    }
 
-    let decision = get_decision();
+    pub fn register_number(&self, name: &str, value: f32) {
+        unsafe {
+            lua_bridge_register_number(self.state, name.to_c_str().as_ptr(), value);
+        }
+    }
 
-    match decision {
-        0 => TradeDecision::NOOP,
-        1 => TradeDecision::LONG,
-        2 => TradeDecision::SHORT,
-        3 => TradeDecision::CLOSE,
-        _ => panic!("unknown value: {}", decision),
+    // for values a float can't hold exactly, e.g. timestamps
+    pub fn register_integer(&self, name: &str, value: i32) {
+        unsafe {
+            lua_bridge_register_integer(self.state, name.to_c_str().as_ptr(), value);
+        }
     }
-}
 
-fn get_decision() -> i32 {
-    unsafe {
-        lua_get_decision()
+    pub fn register_boolean(&self, name: &str, value: bool) {
+        unsafe {
+            lua_bridge_register_boolean(self.state, name.to_c_str().as_ptr(), to_c_bool(value));
+        }
+    }
+
+    // ===== TICK FUNCTIONS ========================================================================
+
+    // Refills the callbacks' tick and ctx tables and the account table, working out the balance
+    // and the rest of the account once.
+    pub fn set_tick(&self, tick: &Tick, sim: &Simulation) {
+        let t = tick.time;
+
+        let tick = CTick {
+            time: t.to_timespec().sec as libc::c_double,
+            bid: tick.exact_bid,
+            ask: tick.exact_ask,
+            hour: t.tm_hour,
+            minute: t.tm_min,
+            weekday: sessions::weekday(t),
+            day: t.tm_mday,
+            asian_open: to_c_bool(Session::Asian.is_open(t)),
+            sydney_open: to_c_bool(Session::Sydney.is_open(t)),
+            tokyo_open: to_c_bool(Session::Tokyo.is_open(t)),
+            london_open: to_c_bool(Session::London.is_open(t)),
+            new_york_open: to_c_bool(Session::NewYork.is_open(t)),
+        };
+
+        let stats = sim.get_tick_statistics();
+
+        let context = CContext {
+            balance: sim.balance() as libc::c_double,
+            equity: sim.equity() as libc::c_double,
+            drawdown: sim.current_drawdown() as libc::c_double,
+            free_margin: sim.free_margin() as libc::c_double,
+            open_trades: sim.open_trades_count() as libc::c_int,
+            tick_velocity: stats.tick_velocity() as libc::c_double,
+            spread_average: stats.spread_average() as libc::c_double,
+            spread_max: stats.spread_max() as libc::c_double,
+            realized_volatility: stats.realized_volatility() as libc::c_double,
+            seconds_since_last_tick: stats.seconds_since_last_tick() as libc::c_double,
+        };
+
+        unsafe {
+            lua_bridge_set_tick(self.state, &tick, &context);
+        }
+    }
+
+    // for call(), the two values count towards its `nargs`
+    pub fn push_tick_arguments(&self) {
+        unsafe {
+            lua_bridge_push_tick_arguments(self.state);
+        }
+    }
+
+    // ===== ACCOUNT FUNCTIONS =====================================================================
+
+    // replaces what positions() returns
+    pub fn publish_positions(&self, trades: &Vec<Trade>) {
+        unsafe {
+            lua_bridge_clear_positions(self.state);
+        }
+
+        for trade in trades.iter() {
+            let comment = trade.comment.to_c_str();
+
+            let position = CPosition {
+                id: trade.get_id(),
+                is_long: to_c_bool(trade.is_long()),
+                size: trade.size,
+                open_price: trade.open_price,
+                open_time: trade.opened_at.to_timespec().sec as libc::c_double,
+                profit: trade.profit(),
+                has_stop_loss: to_c_bool(trade.stop_loss.is_some()),
+                stop_loss: trade.stop_loss.unwrap_or(0.0),
+                has_take_profit: to_c_bool(trade.take_profit.is_some()),
+                take_profit: trade.take_profit.unwrap_or(0.0),
+                magic: trade.magic,
+                comment: comment.as_ptr(),
+            };
+
+            unsafe {
+                lua_bridge_push_position(self.state, &position);
+            }
+        }
+    }
+
+    // For when the same trades are still open: only their profits have changed since
+    // publish_positions().
+    pub fn update_position_profits(&self, trades: &Vec<Trade>) {
+        let profits: Vec<f32> = trades.iter().map( |t| t.profit() ).collect();
+
+        unsafe {
+            lua_bridge_set_position_profits(
+                self.state,
+                profits.as_ptr(),
+                profits.len() as libc::c_int,
+            );
+        }
+    }
+
+    // ===== CALLBACK FUNCTIONS ====================================================================
+
+    pub fn has_function(&self, name: &str) -> bool {
+        unsafe {
+            0 != lua_bridge_has_function(self.state, name.to_c_str().as_ptr())
+        }
+    }
+
+    // Calls the strategy's `name` function, if it has one, with the `nargs` values pushed onto
+    // the stack last, e.g. a table built with create_table().
+    pub fn call(&self, name: &str, nargs: i32) -> Result<(), LuaError> {
+        match unsafe { lua_bridge_call(self.state, name.to_c_str().as_ptr(), nargs) } {
+            0 => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    // ===== OUTPUT FUNCTIONS ======================================================================
+
+    // The log() and plot() calls made since the last call, in the order they were made.
+    pub fn take_output(&self) -> (Vec<LogMessage>, Vec<PlotPoint>) {
+        let log_count  = unsafe { lua_bridge_log_count(self.state) };
+        let plot_count = unsafe { lua_bridge_plot_count(self.state) };
+
+        let messages = range(0, log_count).map( |index| self.get_log(index) ).collect();
+        let points   = range(0, plot_count).map( |index| self.get_plot(index) ).collect();
+
+        unsafe {
+            lua_bridge_clear_output(self.state);
+        }
+
+        (messages, points)
+    }
+
+    fn get_log(&self, index: i32) -> LogMessage {
+        let mut message = CLogMessage { level: ptr::null(), message: ptr::null() };
+
+        unsafe {
+            lua_bridge_get_log(self.state, index, &mut message);
+
+            LogMessage {
+                level: to_string(message.level),
+                message: to_string(message.message),
+            }
+        }
+    }
+
+    fn get_plot(&self, index: i32) -> PlotPoint {
+        let mut point = CPlotPoint { series: ptr::null(), value: 0.0, chart: ptr::null() };
+
+        unsafe {
+            lua_bridge_get_plot(self.state, index, &mut point);
+
+            PlotPoint {
+                series: to_string(point.series),
+                value: point.value as f32,
+                chart: match point.chart.is_null() {
+                    true  => None,
+                    false => Some(to_string(point.chart)),
+                },
+            }
+        }
+    }
+
+    // ===== TRADING FUNCTIONS =====================================================================
+
+    // The orders placed since the last call, in the order they were placed.
+    pub fn take_orders(&self) -> Vec<Order> {
+        let count = unsafe { lua_bridge_order_count(self.state) };
+
+        let orders = range(0, count).map( |index| self.get_order(index) ).collect();
+
+        unsafe {
+            lua_bridge_clear_orders(self.state);
+        }
+
+        orders
+    }
+
+    fn get_order(&self, index: i32) -> Order {
+        let mut order = COrder {
+            action: 0,
+            id: 0,
+            size: 0.0,
+            has_stop_loss: 0,
+            stop_loss: 0.0,
+            has_take_profit: 0,
+            take_profit: 0.0,
+            magic: 0,
+            comment: ptr::null(),
+        };
+
+        unsafe {
+            lua_bridge_get_order(self.state, index, &mut order);
+        }
+
+        Order {
+            action: OrderAction::from_int(order.action),
+            id: order.id,
+            size: order.size,
+            stop_loss: match order.has_stop_loss {
+                0 => None,
+                _ => Some(order.stop_loss),
+            },
+            take_profit: match order.has_take_profit {
+                0 => None,
+                _ => Some(order.take_profit),
+            },
+            magic: order.magic,
+            comment: match order.comment.is_null() {
+                true  => String::new(),
+                false => unsafe { to_string(order.comment) },
+            },
+        }
     }
 }
@@ -1,4 +1,5 @@
 use std::io::File;
+use std::rand::{SeedableRng, StdRng};
 
 use algorithm::Algorithm;
 use chart::Chart;
@@ -9,20 +10,27 @@ use tick::Tick;
 
 pub struct Optimizer {
     strategy: Strategy,
+
+    // picks the variables, seeded from SEED and the walk forward step
+    rng: StdRng,
 }
 
 impl Optimizer {
-    pub fn new(strategy: Strategy) -> Optimizer {
+    pub fn new(strategy: Strategy, step: i32) -> Optimizer {
+        let seed: &[uint] = &[config::get().seed as uint, step as uint];
+
         Optimizer {
             strategy: strategy,
+            rng: SeedableRng::from_seed(seed),
         }
     }
 
-    pub fn variables_for(&self,
+    pub fn variables_for(&mut self,
                         charts: Vec<Chart>,
                         ticks: &Vec<Tick>,
                         tradelog: &mut File,
-                        ticklog: &mut File) -> Option<RangeBoundVariables> {
+                        ticklog: &mut File,
+                        chartlog: &mut File) -> Option<RangeBoundVariables> {
         let mut best_variables = RangeBoundVariables::new();
         let mut best_score = -999999.0;
         let mut successful_algorithm_count = 0i;
@@ -30,17 +38,28 @@ impl Optimizer {
         let max_iterations = config::get().iterations;
 
         for i in range(1i32, max_iterations + 1) {
-            let config_variables = config::get().variables.clone();
-            let mut vars = RangeBoundVariables::new_from_string(config_variables);
-            vars.randomize();
+            let mut vars = RangeBoundVariables::new_from_settings(&config::get().variables);
+            vars.randomize(&mut self.rng);
 
             let mut algorithm = Algorithm::new_in_sample(self.strategy.clone(), charts.clone());
 
             println!("-------------------- TEST {} --------------------", i);
 
-            let score: f32 = match algorithm.execute_on(ticks, vars.clone(), tradelog, ticklog) {
-                Some(score) => score,
-                None        => continue,
+            let score: f32 = match algorithm.execute_on(
+                ticks,
+                vars.clone(),
+                tradelog,
+                ticklog,
+                chartlog,
+            ) {
+                Ok(Some(score)) => score,
+                Ok(None)        => continue,
+                Err(error)      => {
+                    // only this variable set failed, so the others still get their turn
+                    error.print();
+                    println!("Test {} failed", i);
+                    continue;
+                },
             };
 
             if score > best_score {
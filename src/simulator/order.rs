@@ -0,0 +1,55 @@
+// Orders a strategy places from Lua with buy{}, sell{}, close(), close_all() and modify().  They
+// are collected while a callback such as on_tick() runs and carried out in the order they were
+// placed once it returns, see Simulation::execute_order().
+
+// keep in sync with ORDER_ACTION in bridge.c
+#[derive(Clone,Copy,Show,PartialEq)]
+pub enum OrderAction {
+    Buy,
+    Sell,
+    Close,
+    CloseAll,
+    Modify,
+}
+
+impl OrderAction {
+    pub fn from_int(action: i32) -> OrderAction {
+        match action {
+            0 => OrderAction::Buy,
+            1 => OrderAction::Sell,
+            2 => OrderAction::Close,
+            3 => OrderAction::CloseAll,
+            4 => OrderAction::Modify,
+            _ => panic!("unknown order action: {}", action),
+        }
+    }
+}
+
+#[derive(Clone,Show)]
+pub struct Order {
+    pub action: OrderAction,
+
+    // the trade to open, close or modify, 0 for close_all()
+    pub id: i32,
+
+    // multiplies the trade's pip profit, 1 unless the strategy set it
+    pub size: f32,
+
+    // Prices the trade is closed at.  None when the strategy left them out, Some(0.0) when it
+    // asked for them to be removed.
+    pub stop_loss: Option<f32>,
+    pub take_profit: Option<f32>,
+
+    pub magic: i32,
+    pub comment: String,
+}
+
+// ===== GLOBAL FUNCTIONS ==========================================================================
+
+// turns the 0 that removes a stop into None
+pub fn stop_level(price: Option<f32>) -> Option<f32> {
+    match price {
+        Some(price) if price > 0.0 => Some(price),
+        _                          => None,
+    }
+}
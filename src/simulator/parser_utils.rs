@@ -3,9 +3,9 @@ pub fn empty_or_comment(line: &str) -> bool {
     0 == line.len() || line.char_at(0) == '/' && line.char_at(1) == '/'
 }
 
-pub fn validate_name(name: &str) {
+pub fn validate_name(name: &str) -> Result<(), String> {
     if 0 == name.len() {
-        panic!("variable name must not be empty");
+        return Err("name must not be empty".to_string());
     }
 
     for c in name.chars() {
@@ -14,7 +14,9 @@ pub fn validate_name(name: &str) {
         let special = c == '_' || c == '-';
         
         if !alpha && !numeric && !special {
-            panic!("invalid character in variable name: {}", c);
+            return Err(format!("invalid character in name {}: {}", name, c));
         }
     }
+
+    Ok(())
 }
@@ -1,60 +1,325 @@
 use std::io::{BufferedReader,File,MemReader};
-
-use chart::Chart;
-use indicators::Indicator;
+use std::str::FromStr;
+
+use chart;
+use chart::{Chart, PriceSource};
+use config;
+use config::{ChartSettings, ConfigError, CrossIndicatorSettings, IndicatorParam};
+use config::{IndicatorSettings, VariableKind, VariableSettings};
+use cross_indicators::{CrossIndicator, CrossIndicatorType};
+use indicators::{ChartIndicator, IndicatorDefinition};
+use indicators::registry;
+use indicators::registry::ParameterKind;
 use range_bound_variable::RangeBoundVariables;
 use parser_utils;
 use utilities;
 
+fn check_name(path: &str, name: &str) -> Result<(), ConfigError> {
+    parser_utils::validate_name(name).map_err( |message| ConfigError::new(path, message) )
+}
+
 // ===== CHARTS ====================================================================================
 
+// "lua,my_indicator[,name=alias]" uses the function the strategy registered with
+// register_indicator("my_indicator", f).  The Lua name is "#{chart}_my_indicator".
+fn parse_lua_indicator(lua_chart_name: &str,
+                       settings: &IndicatorSettings) -> Result<ChartIndicator, ConfigError> {
+    let path = settings.path.as_slice();
+
+    let function = match settings.function {
+        Some(ref function) => function.as_slice(),
+        None               => return Err(ConfigError::new(
+            path,
+            "Lua indicators need the name of the function to call".to_string()
+        )),
+    };
+
+    try!(check_name(path, function));
+
+    if 0 != settings.params.len() {
+        return Err(ConfigError::new(
+            path,
+            "Lua indicators only take the function's name and options".to_string()
+        ));
+    }
+
+    match settings.options.first() {
+        Some(&(ref key, _)) => return Err(ConfigError::new(
+            path,
+            format!("unknown indicator option: {}", key)
+        )),
+        None                => {},
+    }
+
+    let alias = match settings.name {
+        Some(ref alias) => alias.as_slice(),
+        None            => function,
+    };
+
+    let lua_indicator_name = format!("{}_{}", lua_chart_name, alias);
+    try!(check_name(path, lua_indicator_name.as_slice()));
+
+    let mut indi = ChartIndicator::new_lua(lua_indicator_name.as_slice(), lua_chart_name, function);
+
+    if settings.history > 0 {
+        indi.set_history_length(settings.history as uint);
+    }
+
+    Ok(indi)
+}
+
 fn parse_indicator(lua_chart_name: &str,
-                   indicator_string: &str,
-                   num_chart_candles: i32) -> Indicator {
-    let indicator_parts = utilities::split_csv_string(indicator_string, ',');
+                   settings: &IndicatorSettings,
+                   num_chart_candles: i32) -> Result<ChartIndicator, ConfigError> {
+    let path = settings.path.as_slice();
+    let indicator_type = settings.indicator_type.as_slice();
 
-    if 2 != indicator_parts.len() {
-        panic!("indicator definition must have 2 parts");
+    if "lua" == indicator_type {
+        return parse_lua_indicator(lua_chart_name, settings);
     }
 
-    let indicator_type        = indicator_parts[0];
-    let num_indicator_candles = utilities::string_to_int(indicator_parts[1]) as i32;
+    let registered = match registry::get().lookup(indicator_type) {
+        Some(registered) => registered,
+        None             => return Err(ConfigError::new(
+            path,
+            format!("unknown indicator type: {}", indicator_type)
+        )),
+    };
 
-    if num_indicator_candles < 1 {
-        panic!("number of candles for indicator must be > 0");
-    } else if num_indicator_candles > num_chart_candles {
-        panic!(
-            "number of indicator candles ({}) can't exceed number of chart candles ({})",
-            num_chart_candles,
-            num_indicator_candles,
-        );
+    // so a typo like "histroy=5" doesn't silently run with the default
+    for &(ref key, _) in settings.options.iter() {
+        if !registered.options.iter().any( |&option| option == key.as_slice() ) {
+            return Err(ConfigError::new(path, format!("unknown indicator option: {}", key)));
+        }
     }
 
-    // e.g., "candlestick_M1_sma_60" from the example above
-    let lua_indicator_name = format!("{}_{}_{}", lua_chart_name, indicator_type, num_indicator_candles);
-    parser_utils::validate_name(lua_indicator_name.as_slice());
+    let mut name_parts: Vec<&str> = vec!();
+    let mut params: Vec<f32> = vec!();
+    let mut variables: Vec<Option<String>> = vec!();
+    let mut source: Option<PriceSource> = None;
+    let mut input: Option<String> = None;
+
+    // a price source or an input is part of the Lua name, unlike options
+    for param in settings.params.iter() {
+        match *param {
+            IndicatorParam::Number(value, ref text) => {
+                name_parts.push(text.as_slice());
+                params.push(value);
+                variables.push(None);
+            },
+            IndicatorParam::Variable(ref name) => {
+                name_parts.push(name.as_slice());
+                params.push(0.0); // placeholder until the variables are bound
+                variables.push(Some(name.clone()));
+            },
+            IndicatorParam::Source(s, ref text) => {
+                if source.is_some() {
+                    return Err(ConfigError::new(
+                        path,
+                        "indicator has more than one price source".to_string()
+                    ));
+                }
+
+                source = Some(s);
+                name_parts.push(text.as_slice());
+            },
+            IndicatorParam::Input(ref name) => {
+                if input.is_some() {
+                    return Err(ConfigError::new(
+                        path,
+                        "indicator has more than one input".to_string()
+                    ));
+                }
+
+                input = Some(format!("{}_{}", lua_chart_name, name));
+                name_parts.push(name.as_slice());
+            },
+        }
+    }
 
-    let indi = match indicator_type {
-        "sma" => {
-            Indicator::new_sma(lua_indicator_name.as_slice(), num_indicator_candles)
-        },
-        _     => panic!("unknown indicator type: {}", indicator_type)
+    if source.is_some() && input.is_some() {
+        return Err(ConfigError::new(
+            path,
+            "indicator can't have both a price source and an input".to_string()
+        ));
+    }
+
+    if params.len() != registered.parameters.len() {
+        return Err(ConfigError::new(path, format!(
+            "indicator {} takes {} parameter(s), got {}",
+            indicator_type,
+            registered.parameters.len(),
+            params.len()
+        )));
+    }
+
+    // variables are checked over their whole range by Chart::check_variables()
+    for (index, &kind) in registered.parameters.iter().enumerate() {
+        let param = params[index];
+
+        if variables[index].is_some() {
+            continue;
+        }
+
+        if param <= 0.0 {
+            return Err(ConfigError::new(
+                path,
+                format!("parameter {} must be > 0, got {}", index + 1, param)
+            ));
+        }
+
+        if ParameterKind::Period == kind && param != param.floor() {
+            return Err(ConfigError::new(path, format!(
+                "parameter {} is a period and must be a whole number, got {}",
+                index + 1,
+                param
+            )));
+        }
+    }
+
+    // e.g., "candlestick_M1_sma_60" or "candlestick_M1_bollinger_20_2p5".  Decimal points aren't
+    // valid in Lua names so they become a "p".  An alias replaces everything after the chart name.
+    let lua_indicator_name = match settings.name {
+        Some(ref alias) => format!("{}_{}", lua_chart_name, alias),
+        None if name_parts.is_empty() => format!("{}_{}", lua_chart_name, indicator_type),
+        None            => format!(
+            "{}_{}_{}",
+            lua_chart_name,
+            indicator_type,
+            name_parts.connect("_").replace(".", "p")
+        ),
     };
+    try!(check_name(path, lua_indicator_name.as_slice()));
+
+    let name = lua_indicator_name.as_slice();
+    let mut definition = IndicatorDefinition::new(name, indicator_type, params);
+    definition.variables = variables;
+    definition.options = settings.options.iter().map( |&(ref k, v)| (k.clone(), v) ).collect();
+
+    match source {
+        Some(s) => definition.source = s,
+        None    => {},
+    }
 
-    println!("Loaded indicator {}", lua_indicator_name);
+    let mut indi = ChartIndicator::from_definition(definition);
+
+    // An indicator reading another indicator waits for that one's history instead.  Variable
+    // periods are checked against the chart by Chart::check_variables().
+    if input.is_none() && indi.is_bound() && indi.candles_required() > num_chart_candles {
+        return Err(ConfigError::new(path, format!(
+            "indicator {} needs {} candles but the chart only has {}",
+            lua_indicator_name,
+            indi.candles_required(),
+            num_chart_candles
+        )));
+    }
+
+    match input {
+        Some(ref name) => indi.set_input(name.as_slice()),
+        None           => {},
+    }
 
-    indi
+    indi.set_history_length(settings.history as uint);
+
+    Ok(indi)
 }
 
-fn parse_charts<T: Buffer>(buffer: &mut T) -> Vec<Chart> {
-    let mut charts: Vec<Chart> = vec!();
-    let mut num_loaded = 0i32;
+// One of an indicator's parameters as written, e.g. "20", "$fast_period", "hlc3_mid" or
+// "rsi_14".  See parse_charts() for what each one means.
+pub fn parse_indicator_param(path: &str, text: &str) -> Result<IndicatorParam, ConfigError> {
+    if text.starts_with("$") {
+        let variable = text.slice_from(1);
+        try!(check_name(path, variable));
+
+        return Ok(IndicatorParam::Variable(variable.to_string()));
+    }
+
+    match PriceSource::from_string(text) {
+        Some(source) => return Ok(IndicatorParam::Source(source, text.to_string())),
+        None         => {},
+    }
+
+    match text.parse::<f32>() {
+        Some(value) => Ok(IndicatorParam::Number(value, text.to_string())),
+        None        => {
+            try!(check_name(path, text));
+            Ok(IndicatorParam::Input(text.to_string()))
+        },
+    }
+}
+
+// an indicator in its "type,param[,param...][,key=value...]" form
+fn parse_indicator_settings(path: String,
+                            indicator_string: &str) -> Result<IndicatorSettings, ConfigError> {
+    let parts = utilities::split_csv_string(indicator_string, ',');
+    let indicator_type = parts[0];
+
+    let mut function: Option<String> = None;
+    let mut params: Vec<IndicatorParam> = vec!();
+    let mut name: Option<String> = None;
+    let mut history = 0i32;
+    let mut options: Vec<(String, f32)> = vec!();
+
+    for &part in parts.slice_from(1).iter() {
+        let option: Vec<&str> = part.splitn(1, '=').collect();
+
+        if 2 == option.len() {
+            match option[0] {
+                "name"    => name = Some(option[1].to_string()),
+                "history" => {
+                    history = match option[1].parse::<i32>() {
+                        Some(history) if history > 0 => history,
+                        _                            => return Err(ConfigError::new(
+                            path.as_slice(),
+                            format!("history must be a whole number > 0, got {}", option[1])
+                        )),
+                    };
+                },
+                // anything else is for the indicator itself, e.g. "doji_body=0.05"
+                key       => {
+                    match option[1].parse::<f32>() {
+                        Some(value) => options.push((key.to_string(), value)),
+                        None        => return Err(ConfigError::new(
+                            path.as_slice(),
+                            format!("indicator option {} must be a number", key)
+                        )),
+                    }
+                },
+            }
+
+            continue;
+        }
+
+        if "lua" == indicator_type && function.is_none() {
+            function = Some(part.to_string());
+            continue;
+        }
+
+        params.push(try!(parse_indicator_param(path.as_slice(), part)));
+    }
+
+    Ok(IndicatorSettings {
+        path: path,
+        indicator_type: indicator_type.to_string(),
+        function: function,
+        params: params,
+        name: name,
+        history: history,
+        options: options,
+    })
+}
+
+fn parse_charts<T: Buffer>(buffer: &mut T) -> Result<Vec<ChartSettings>, ConfigError> {
+    let mut charts: Vec<ChartSettings> = vec!();
+    let mut line_number = 0i32;
 
     for mut line in buffer.lines().filter_map( |result| result.ok() ) {
         let old_len = line.len();
         let length = old_len - 1;
         line.truncate(length);
 
+        line_number += 1;
+
         // skip empty lines and comment lines
         if parser_utils::empty_or_comment(line.as_slice()) {
             continue;
@@ -63,61 +328,146 @@ fn parse_charts<T: Buffer>(buffer: &mut T) -> Vec<Chart> {
         // Example line: candlestick,M1,60|sma,12
         // Chart is "type,period,num_candles".  Lua variable is just "#{type}_#{period}",
         // e.g., "candlestick_M1".
-        // Indicators follow the first pipe.  Format is "type,num_candles".  Lua variable is
-        // "#{chart_type}_#{chart_period}_#{indicator_name}_#{num_indicator_candles}"
+        // Indicators follow the first pipe.  Format is "type,param[,param...]", e.g. "sma,12" or
+        // "bollinger,20,2.0".  Types are looked up in indicators::registry.  Lua variable is
+        // "#{chart_type}_#{chart_period}_#{indicator_name}_#{params}" with the parameters joined by
+        // underscores.  Indicators with several outputs (MACD, bands, ...) publish one variable per
+        // output with a suffix, e.g. "_upper", "_middle" and "_lower".
+        // A price source such as "hlc3_mid" may follow the parameters (see chart::PriceSource),
+        // e.g. "sma,20,hlc3_mid".  The default is "close_bid".
+        // Instead of a price source an indicator can read another indicator on the same chart by
+        // its name without the chart prefix, e.g. "rsi,14:sma,10,rsi_14" for an SMA of RSI.
+        // Inputs are updated first; cycles are a configuration error.
+        // Any parameter can be "$variable" to take its value from VARIABLES on each optimizer
+        // iteration, e.g. "sma,$fast_period".  The variable's name stands in for the value in the
+        // Lua name, or "name=fast_ma" sets the part after the chart name explicitly.
+        // "lua,my_indicator" is calculated by a function the strategy script registered with
+        // register_indicator("my_indicator", function(candles, state) ... return value, state end).
+        // Options follow the parameters as "key=value".  "history=N" keeps the last N values of
+        // each output and publishes them as "#{lua_variable}_history", indexed from 0.  Other
+        // options are passed to the indicator, e.g. "patterns,doji_body=0.05" for candlestick
+        // patterns (see indicators::patterns), which publishes e.g. "candlestick_M1_patterns_doji".
+        // Level indicators like "pivots", "prev_day" or "vwap_london" take no parameters and are
+        // built from the ticks (see indicators::levels), e.g. "candlestick_H1_pivots_r1".
+
+        let path = format!("CHARTS line {}", line_number);
 
         let parts = utilities::split_csv_string(line.as_slice(), '|');
 
         if parts.len() < 2 {
-            panic!("line must have > 2 parts");
+            return Err(ConfigError::new(path.as_slice(), "line must have > 2 parts".to_string()));
         }
 
         let chart_section     = parts[0];
         let indicator_section = parts[1];
 
-        // ----- CREATE CHART ----------------------------------------------------------------------
-
         let chart_parts = utilities::split_csv_string(chart_section, ',');
 
         if 3 != chart_parts.len() {
-            panic!("chart definition must have 3 parts")
+            return Err(ConfigError::new(
+                path.as_slice(),
+                "chart definition must have 3 parts".to_string()
+            ));
+        }
+
+        let candles = match chart_parts[2].parse::<i32>() {
+            Some(candles) => candles,
+            None          => return Err(ConfigError::new(
+                path.as_slice(),
+                format!("number of candles must be a whole number, got {}", chart_parts[2])
+            )),
+        };
+
+        let mut indicators: Vec<IndicatorSettings> = vec!();
+
+        let indicator_strings = utilities::split_csv_string(indicator_section, ':');
+
+        for (index, &indicator_string) in indicator_strings.iter().enumerate() {
+            let indicator_path = format!("{}, indicator {}", path, index + 1);
+            indicators.push(try!(parse_indicator_settings(indicator_path, indicator_string)));
         }
 
-        let chart_type  = chart_parts[0];
-        let period      = chart_parts[1];
-        let num_chart_candles = utilities::string_to_int(chart_parts[2]) as i32;
+        charts.push(ChartSettings {
+            path: path.clone(),
+            chart_type: chart_parts[0].to_string(),
+            period: chart_parts[1].to_string(),
+            candles: candles,
+            indicators: indicators,
+        });
+    }
+
+    Ok(charts)
+}
+
+// `report` prints what was loaded, which the check when the config is loaded leaves out
+pub fn build_charts(settings: &Vec<ChartSettings>,
+                    report: bool) -> Result<Vec<Chart>, ConfigError> {
+    let mut charts: Vec<Chart> = vec!();
+
+    for chart_settings in settings.iter() {
+        let path = chart_settings.path.as_slice();
+
+        // ----- CREATE CHART ----------------------------------------------------------------------
+
+        let chart_type  = chart_settings.chart_type.as_slice();
+        let period      = chart_settings.period.as_slice();
+        let num_chart_candles = chart_settings.candles;
 
         if num_chart_candles < 1 {
-            panic!("number of candles for chart must be > 0");
+            return Err(ConfigError::new(
+                path,
+                "number of candles for chart must be > 0".to_string()
+            ));
+        }
+
+        if chart::period_from_string(period).is_none() {
+            return Err(ConfigError::new(path, format!("unknown period: {}", period)));
         }
 
         let lua_chart_name = format!("{}_{}", chart_type, period);
-        parser_utils::validate_name(lua_chart_name.as_slice());
+        try!(check_name(path, lua_chart_name.as_slice()));
+
+        let name = lua_chart_name.as_slice();
 
         let mut chart = match chart_type {
-            "candlestick" => Chart::new_candlestick_chart(lua_chart_name.as_slice(), period, num_chart_candles),
-            _             => panic!("unknown chart type: {}", chart_type),
+            "candlestick" => Chart::new_candlestick_chart(name, period, num_chart_candles),
+            _             => return Err(ConfigError::new(
+                path,
+                format!("unknown chart type: {}", chart_type)
+            )),
         };
 
-        println!("Loaded chart {}", lua_chart_name);
+        if report {
+            println!("Loaded chart {}", lua_chart_name);
+        }
 
         // ----- CREATE AND ATTACH INDICATORS ------------------------------------------------------
 
-        for &indicator_string in utilities::split_csv_string(indicator_section, ':').iter() {
-            let x = parse_indicator(lua_chart_name.as_slice(), indicator_string, num_chart_candles);
+        for indicator in chart_settings.indicators.iter() {
+            let x = try!(parse_indicator(lua_chart_name.as_slice(), indicator, num_chart_candles));
+
+            if report {
+                match indicator.indicator_type.as_slice() {
+                    "lua" => println!("Loaded Lua indicator {}", x.get_name()),
+                    _     => println!("Loaded indicator {}", x.get_name()),
+                }
+            }
+
             chart.attach_indicator(x);
         }
 
+        try!(chart.resolve_indicator_inputs().map_err( |message| ConfigError::new(path, message) ));
+
         // ----- APPEND CHART ----------------------------------------------------------------------
 
         charts.push(chart);
-
-        num_loaded += 1;
     }
 
-    println!("Loaded {} charts", num_loaded);
+    if report {
+        println!("Loaded {} charts", charts.len());
+    }
 
-    charts
+    Ok(charts)
 }
 
 pub fn parse_charts_from_file(path: &str) -> Vec<Chart> {
@@ -129,60 +479,283 @@ pub fn parse_charts_from_file(path: &str) -> Vec<Chart> {
 
     println!("Loading charts and indicators from {}", path);
 
-    parse_charts(&mut reader)
+    let settings = config::expect_valid(parse_charts(&mut reader));
+    config::expect_valid(build_charts(&settings, true))
 }
 
 pub fn parse_charts_from_string(s: String) -> Vec<Chart> {
-    let mut reader = MemReader::new(s.into_bytes());
-
     println!("Loading charts and indicators from string");
 
+    let settings = config::expect_valid(parse_chart_settings(s));
+    config::expect_valid(build_charts(&settings, true))
+}
+
+pub fn parse_chart_settings(s: String) -> Result<Vec<ChartSettings>, ConfigError> {
+    let mut reader = MemReader::new(s.into_bytes());
+
     parse_charts(&mut reader)
 }
 
+// ===== CROSS INDICATORS ==========================================================================
+
+fn parse_cross_indicators<T: Buffer>(buffer: &mut T)
+                                     -> Result<Vec<CrossIndicatorSettings>, ConfigError> {
+    let mut indicators: Vec<CrossIndicatorSettings> = vec!();
+    let mut line_number = 0i32;
+
+    for mut line in buffer.lines().filter_map( |result| result.ok() ) {
+        let old_len = line.len();
+        let length = old_len - 1;
+        line.truncate(length);
+
+        line_number += 1;
+
+        // skip empty lines and comment lines
+        if parser_utils::empty_or_comment(line.as_slice()) {
+            continue;
+        }
+
+        // Example line: correlation,candlestick_M5,candlestick_M15,50
+        // Format is "type,chart_a,chart_b,period" with the charts' Lua names.  Types are
+        // "correlation", "ratio", "zscore" (of the log price spread) and "beta" (of A on B).
+        // "ratio" has no period.  Lua variable is "#{type}_#{chart_a}_#{chart_b}_#{period}", or
+        // "name=alias" at the end sets it explicitly.
+
+        let path = format!("CROSS_INDICATORS line {}", line_number);
+
+        let parts = utilities::split_csv_string(line.as_slice(), ',');
+
+        if parts.len() < 3 {
+            return Err(ConfigError::new(
+                path.as_slice(),
+                "cross indicator definition must have at least 3 parts".to_string()
+            ));
+        }
+
+        let mut period: Option<i32> = None;
+        let mut alias: Option<String> = None;
+
+        for &part in parts.slice_from(3).iter() {
+            let option: Vec<&str> = part.splitn(1, '=').collect();
+
+            match option.len() {
+                2 if "name" == option[0] => alias = Some(option[1].to_string()),
+                2                        => return Err(ConfigError::new(
+                    path.as_slice(),
+                    format!("unknown cross indicator option: {}", option[0])
+                )),
+                _                        => {
+                    period = match part.parse::<i32>() {
+                        Some(period) => Some(period),
+                        None         => return Err(ConfigError::new(
+                            path.as_slice(),
+                            format!("period must be a whole number, got {}", part)
+                        )),
+                    };
+                },
+            }
+        }
+
+        indicators.push(CrossIndicatorSettings {
+            path: path.clone(),
+            indicator_type: parts[0].to_string(),
+            chart_a: parts[1].to_string(),
+            chart_b: parts[2].to_string(),
+            period: period,
+            name: alias,
+        });
+    }
+
+    Ok(indicators)
+}
+
+// `charts` are the ones built from the same config, which the indicators are checked against.
+// `report` is the same as build_charts()'.
+pub fn build_cross_indicators(settings: &Vec<CrossIndicatorSettings>,
+                              charts: &Vec<Chart>,
+                              report: bool) -> Result<Vec<CrossIndicator>, ConfigError> {
+    let mut indicators: Vec<CrossIndicator> = vec!();
+
+    for indicator in settings.iter() {
+        let path = indicator.path.as_slice();
+
+        let indicator_type = indicator.indicator_type.as_slice();
+        let chart_a = indicator.chart_a.as_slice();
+        let chart_b = indicator.chart_b.as_slice();
+
+        let it = match CrossIndicatorType::from_string(indicator_type) {
+            Some(it) => it,
+            None     => return Err(ConfigError::new(
+                path,
+                format!("unknown cross indicator type: {}", indicator_type)
+            )),
+        };
+
+        let period = match (it, indicator.period) {
+            (CrossIndicatorType::Ratio, None) => 0,
+            (_, Some(period)) if period > 0   => period as uint,
+            _                                 => return Err(ConfigError::new(
+                path,
+                format!("{} needs a period > 0", indicator_type)
+            )),
+        };
+
+        let name = match (&indicator.name, period) {
+            (&Some(ref alias), _) => alias.clone(),
+            (&None, 0)            => format!("{}_{}_{}", indicator_type, chart_a, chart_b),
+            (&None, _)            => format!(
+                "{}_{}_{}_{}",
+                indicator_type,
+                chart_a,
+                chart_b,
+                period
+            ),
+        };
+        try!(check_name(path, name.as_slice()));
+
+        let mut indexes: Vec<uint> = vec!();
+
+        for &chart_name in [chart_a, chart_b].iter() {
+            match charts.iter().position( |c| c.get_name() == chart_name ) {
+                Some(index) => indexes.push(index),
+                None        => return Err(ConfigError::new(
+                    path,
+                    format!("there's no chart named {}", chart_name)
+                )),
+            }
+        }
+
+        let (a, b) = (indexes[0], indexes[1]);
+
+        let mut cross = CrossIndicator::new(name.as_slice(), it, chart_a, chart_b, period);
+        cross.set_chart_indexes(a, b);
+
+        match cross.check_history(&charts[a], &charts[b]) {
+            Ok(())       => {},
+            Err(message) => return Err(ConfigError::new(path, message)),
+        }
+
+        indicators.push(cross);
+    }
+
+    if report {
+        println!("Loaded {} cross indicators", indicators.len());
+    }
+
+    Ok(indicators)
+}
+
+pub fn parse_cross_indicator_settings(s: String)
+                                      -> Result<Vec<CrossIndicatorSettings>, ConfigError> {
+    let mut reader = MemReader::new(s.into_bytes());
+
+    parse_cross_indicators(&mut reader)
+}
+
 // ===== RANGE-BOUND VARIABLES =====================================================================
 
-fn parse_variables<T: Buffer>(buffer: &mut T) -> RangeBoundVariables {
-    let mut rbv = RangeBoundVariables::new();
-    let mut num_loaded = 0i32;
+// a bound of a "float" or "int" variable in its string form
+fn parse_bound<T: FromStr>(path: &str, parts: &Vec<&str>, index: uint) -> Result<T, ConfigError> {
+    let part = match parts.get(index) {
+        Some(&part) => part,
+        None        => return Err(ConfigError::new(
+            path,
+            "float and int variables need a lower and an upper bound".to_string()
+        )),
+    };
+
+    match part.parse::<T>() {
+        Some(bound) => Ok(bound),
+        None        => Err(ConfigError::new(path, format!("invalid bound: {}", part))),
+    }
+}
+
+fn parse_variables<T: Buffer>(buffer: &mut T) -> Result<Vec<VariableSettings>, ConfigError> {
+    let mut variables: Vec<VariableSettings> = vec!();
+    let mut line_number = 0i32;
 
     for mut line in buffer.lines().filter_map( |result| result.ok() ) {
         let old_len = line.len();
         let length = old_len - 1;
         line.truncate(length);
 
+        line_number += 1;
+
         // skip empty lines and comment lines
         if parser_utils::empty_or_comment(line.as_slice()) {
             continue;
         }
 
+        let path = format!("VARIABLES line {}", line_number);
+        let path = path.as_slice();
+
         let parts: Vec<&str> = line.as_slice().split(',').collect();
+
+        if parts.len() < 2 {
+            return Err(ConfigError::new(path, "variable needs a name and a type".to_string()));
+        }
+
         let name = parts[0];
         let var_type = parts[1];
 
-        parser_utils::validate_name(name);
-
-        match var_type {
-            "bool"  => rbv.create_bool(name),
-            "float" => {
-                let lower_bound = utilities::string_to_float(parts[2]);
-                let upper_bound = utilities::string_to_float(parts[3]);
-                rbv.create_float(name, lower_bound, upper_bound)
-            },
-            "int"   => {
-                let lower_bound = utilities::string_to_int(parts[2]);
-                let upper_bound = utilities::string_to_int(parts[3]);
-                rbv.create_int(name, lower_bound, upper_bound)
-            },
-            _       => panic!("unknown variable type: {}", var_type)
+        let kind = match var_type {
+            "bool"  => VariableKind::Bool,
+            "float" => VariableKind::Float(
+                try!(parse_bound(path, &parts, 2)),
+                try!(parse_bound(path, &parts, 3))
+            ),
+            "int"   => VariableKind::Int(
+                try!(parse_bound(path, &parts, 2)),
+                try!(parse_bound(path, &parts, 3))
+            ),
+            _       => return Err(ConfigError::new(
+                path,
+                format!("unknown variable type: {}", var_type)
+            )),
         };
 
-        num_loaded += 1;
+        variables.push(VariableSettings {
+            path: path.to_string(),
+            name: name.to_string(),
+            kind: kind,
+        });
     }
 
-    println!("Loaded {} variables", num_loaded);
+    Ok(variables)
+}
+
+pub fn build_variables(settings: &Vec<VariableSettings>)
+                       -> Result<RangeBoundVariables, ConfigError> {
+    let mut rbv = RangeBoundVariables::new();
+
+    for variable in settings.iter() {
+        let path = variable.path.as_slice();
+        let name = variable.name.as_slice();
+
+        try!(check_name(path, name));
+
+        if rbv.kind_of(name).is_some() {
+            return Err(ConfigError::new(path, format!("there's already a variable {}", name)));
+        }
+
+        let ordered = match variable.kind {
+            VariableKind::Bool                => true,
+            VariableKind::Float(lower, upper) => lower < upper,
+            VariableKind::Int(lower, upper)   => lower < upper,
+        };
+
+        if !ordered {
+            return Err(ConfigError::new(path, "lower bound must be < upper bound".to_string()));
+        }
+
+        match variable.kind {
+            VariableKind::Bool                => rbv.create_bool(name),
+            VariableKind::Float(lower, upper) => rbv.create_float(name, lower, upper),
+            VariableKind::Int(lower, upper)   => rbv.create_int(name, lower, upper),
+        };
+    }
 
-    rbv
+    Ok(rbv)
 }
 
 pub fn parse_variables_from_file(path: &str) -> RangeBoundVariables {
@@ -194,13 +767,46 @@ pub fn parse_variables_from_file(path: &str) -> RangeBoundVariables {
 
     println!("Loading variables from {}", path);
 
-    parse_variables(&mut reader)
+    let settings = config::expect_valid(parse_variables(&mut reader));
+
+    println!("Loaded {} variables", settings.len());
+
+    config::expect_valid(build_variables(&settings))
 }
 
 pub fn parse_variables_from_string(s: String) -> RangeBoundVariables {
-    let mut reader = MemReader::new(s.into_bytes());
-
     println!("Loading variables from string");
 
+    let settings = config::expect_valid(parse_variable_settings(s));
+
+    println!("Loaded {} variables", settings.len());
+
+    config::expect_valid(build_variables(&settings))
+}
+
+pub fn parse_variable_settings(s: String) -> Result<Vec<VariableSettings>, ConfigError> {
+    let mut reader = MemReader::new(s.into_bytes());
+
     parse_variables(&mut reader)
 }
+
+// ===== VALIDATION ================================================================================
+
+// Builds the charts, variables and cross indicators once and checks every variable's range
+// against the indicators it stands in for, so a mistake anywhere in them is reported when the
+// config is loaded instead of at the first simulation or optimizer iteration.
+pub fn check_settings(charts: &Vec<ChartSettings>,
+                      variables: &Vec<VariableSettings>,
+                      cross_indicators: &Vec<CrossIndicatorSettings>) -> Result<(), ConfigError> {
+    let vars = try!(build_variables(variables));
+    let built = try!(build_charts(charts, false));
+
+    for (chart, chart_settings) in built.iter().zip(charts.iter()) {
+        let path = chart_settings.path.as_slice();
+        try!(chart.check_variables(&vars).map_err( |message| ConfigError::new(path, message) ));
+    }
+
+    try!(build_cross_indicators(cross_indicators, &built, false));
+
+    Ok(())
+}
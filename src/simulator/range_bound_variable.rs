@@ -1,10 +1,17 @@
 use std::collections::hash_map;
-use std::rand;
-use std::rand::{thread_rng, Rng};
+use std::rand::Rng;
 
-use lua;
+use config;
+use config::{VariableKind, VariableSettings};
+use lua::Lua;
 use parsers;
 
+fn sorted_names<'a, I: Iterator<Item=&'a String>>(names: I) -> Vec<String> {
+    let mut names: Vec<String> = names.map( |name| name.clone() ).collect();
+    names.sort();
+    names
+}
+
 // ----- BOOL --------------------------------------------------------------------------------------
 
 #[derive(Clone,Show)]
@@ -17,8 +24,8 @@ impl RangeBoundBool {
         RangeBoundBool{ value: false }
     }
 
-    fn randomize(&mut self) {
-        self.value = rand::thread_rng().gen();
+    fn randomize<R: Rng>(&mut self, rng: &mut R) {
+        self.value = rng.gen();
     }
 
     fn value(&self) -> bool {
@@ -44,9 +51,9 @@ impl RangeBoundFloat {
         RangeBoundFloat { lower: lower, upper: upper, value: 0.0f32 }
     }
 
-    fn randomize(&mut self) {
+    fn randomize<R: Rng>(&mut self, rng: &mut R) {
         // gen range is exclusive of the upper bound, but we don't care because it's a float
-        self.value = rand::thread_rng().gen_range(self.lower, self.upper);
+        self.value = rng.gen_range(self.lower, self.upper);
     }
 
     fn value(&self) -> f32 {
@@ -72,9 +79,9 @@ impl RangeBoundInteger {
         RangeBoundInteger { lower: lower, upper: upper, value: 0i32 }
     }
 
-    fn randomize(&mut self) {
+    fn randomize<R: Rng>(&mut self, rng: &mut R) {
         // gen_range is exclusive of the upper bound
-        self.value = rand::thread_rng().gen_range(self.lower, self.upper + 1);
+        self.value = rng.gen_range(self.lower, self.upper + 1);
     }
 
     fn value(&self) -> i32 {
@@ -108,6 +115,10 @@ impl RangeBoundVariables {
         parsers::parse_variables_from_string(text)
     }
 
+    pub fn new_from_settings(settings: &Vec<VariableSettings>) -> RangeBoundVariables {
+        config::expect_valid(parsers::build_variables(settings))
+    }
+
     pub fn create_bool(&mut self, name: &str) {
         if self.bools.contains_key(name) {
             panic!("a bool already exists with the name \"{}\"", name);
@@ -159,6 +170,36 @@ impl RangeBoundVariables {
         }
     }
 
+    // what the optimizer may pick for `name`, for checking it against where it's used
+    pub fn kind_of(&self, name: &str) -> Option<VariableKind> {
+        if self.bools.contains_key(name) {
+            return Some(VariableKind::Bool);
+        }
+
+        match self.floats.get(name) {
+            Some(x) => return Some(VariableKind::Float(x.lower, x.upper)),
+            None    => {},
+        }
+
+        match self.ints.get(name) {
+            Some(x) => Some(VariableKind::Int(x.lower, x.upper)),
+            None    => None,
+        }
+    }
+
+    // integers and floats both work as indicator parameters
+    pub fn get_number(&self, name: &str) -> f32 {
+        match self.ints.get(name) {
+            Some(x) => return x.value() as f32,
+            None    => {},
+        }
+
+        match self.floats.get(name) {
+            Some(x) => x.value(),
+            None    => panic!("could not find a number with the name \"{}\"", name)
+        }
+    }
+
     pub fn print(&self) {
         for (name, value) in self.bools.iter() {
             println!("{} => {}", name, value.value());
@@ -173,31 +214,49 @@ impl RangeBoundVariables {
         }
     }
 
-    pub fn randomize(&mut self) {
-        for (_, value) in self.bools.iter_mut() {
-            value.randomize();
+    // The maps iterate in a different order every run, so the values are drawn in name order for
+    // a seed to give the same ones.
+    pub fn randomize<R: Rng>(&mut self, rng: &mut R) {
+        for name in sorted_names(self.bools.keys()).iter() {
+            self.bools.get_mut(name).unwrap().randomize(rng);
+        }
+
+        for name in sorted_names(self.floats.keys()).iter() {
+            self.floats.get_mut(name).unwrap().randomize(rng);
+        }
+
+        for name in sorted_names(self.ints.keys()).iter() {
+            self.ints.get_mut(name).unwrap().randomize(rng);
+        }
+    }
+
+    pub fn register_in_lua(&self, lua: &Lua) {
+        for (name, value) in self.bools.iter() {
+            lua.register_boolean(name.as_slice(), value.value());
         }
 
-        for (_, value) in self.floats.iter_mut() {
-            value.randomize();
+        for (name, value) in self.floats.iter() {
+            lua.register_number(name.as_slice(), value.value());
         }
 
-        for (_, value) in self.ints.iter_mut() {
-            value.randomize();
+        for (name, value) in self.ints.iter() {
+            lua.register_number(name.as_slice(), value.value() as f32);
         }
     }
+    // leaves a table of the variables by name on top of the stack, for on_start()
+    pub fn push_table(&self, lua: &Lua) {
+        lua.create_table((self.bools.len() + self.floats.len() + self.ints.len()) as i32);
 
-    pub fn register_in_lua(&self) {
         for (name, value) in self.bools.iter() {
-            lua::register_boolean(name.as_slice(), value.value());
+            lua.set_field_boolean(name.as_slice(), value.value());
         }
 
         for (name, value) in self.floats.iter() {
-            lua::register_number(name.as_slice(), value.value());
+            lua.set_field_number(name.as_slice(), value.value());
         }
 
         for (name, value) in self.ints.iter() {
-            lua::register_number(name.as_slice(), value.value() as f32);
+            lua.set_field_number(name.as_slice(), value.value() as f32);
         }
     }
 }
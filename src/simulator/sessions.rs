@@ -0,0 +1,76 @@
+// The trading session calendar.  Tick times are taken to be UTC, and daylight saving time is
+// ignored: the hours are the summer ones, so in the winter every session opens and closes an
+// hour early.  Asian is the session the range and VWAP indicators use; Sydney and Tokyo are
+// there for strategies' session filters.
+
+extern crate time;
+
+#[derive(Clone,Copy,Show,PartialEq)]
+pub enum Session {
+    Asian,
+    Sydney,
+    Tokyo,
+    London,
+    NewYork,
+}
+
+static SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+
+// The FX day ends when New York closes at 21:00 UTC, so a trading day starts 3 hours before
+// midnight.  Sunday evening's ticks are Monday's, and Friday's close is the end of the week.
+static SECONDS_BEFORE_MIDNIGHT: i64 = 60 * 60 * 3;
+
+// 1970-01-01 was a Thursday, so day 4 since the epoch was the first Monday
+static FIRST_MONDAY: i64 = 4;
+
+// days after Sunday that 1970-01-01 was
+static EPOCH_WEEKDAY: i64 = 4;
+
+// ===== GLOBAL FUNCTIONS ==========================================================================
+
+// days since the epoch, changing at midnight
+pub fn day_number(t: time::Tm) -> i64 {
+    t.to_timespec().sec / SECONDS_PER_DAY
+}
+
+// 0 for Sunday through 6 for Saturday.  strptime() doesn't fill in tm_wday, so it's worked out
+// from the date.
+pub fn weekday(t: time::Tm) -> i32 {
+    ((day_number(t) + EPOCH_WEEKDAY) % 7) as i32
+}
+
+// days since the epoch, changing at the New York close (see SECONDS_BEFORE_MIDNIGHT)
+pub fn trading_day_number(t: time::Tm) -> i64 {
+    (t.to_timespec().sec + SECONDS_BEFORE_MIDNIGHT) / SECONDS_PER_DAY
+}
+
+// weeks since the epoch, changing on Sunday evening when the market opens
+pub fn week_number(t: time::Tm) -> i64 {
+    (trading_day_number(t) - FIRST_MONDAY) / 7
+}
+
+// ===== SESSION ===================================================================================
+
+impl Session {
+    // UTC hours the session opens and closes at.  London and New York overlap from 12:00 to
+    // 16:00, Asian and London from 07:00 to 09:00.  Sydney opens the evening before, so it's
+    // the only one that spans midnight, though not the end of the trading day.
+    pub fn hours(&self) -> (i32, i32) {
+        match *self {
+            Session::Asian   => (0, 9),
+            Session::Sydney  => (21, 6),
+            Session::Tokyo   => (0, 9),
+            Session::London  => (7, 16),
+            Session::NewYork => (12, 21),
+        }
+    }
+
+    pub fn is_open(&self, t: time::Tm) -> bool {
+        let (open, close) = self.hours();
+
+        match open < close {
+            true  => t.tm_hour >= open && t.tm_hour < close,
+            false => t.tm_hour >= open || t.tm_hour < close,
+        }
+    }
+}
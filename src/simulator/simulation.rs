@@ -1,12 +1,32 @@
+extern crate time;
+
 use std::io::File;
+use std::mem;
 
 use chart::Chart;
 use config;
+use cross_indicators::CrossIndicator;
+use lua::{Lua, LuaError, LogMessage, PlotPoint};
+use order::{Order, OrderAction};
+use parsers;
+use range_bound_variable::RangeBoundVariables;
 use tick::Tick;
-use trade::Trade;
+use tick_statistics::TickStatistics;
+use trade::{ExitReason, Trade, TradeEvent};
+use utilities;
 
 static mut SIMULATION_ID: i32 = 1;
 
+// units of the base currency a trade of size 1 buys or sells
+static TRADE_UNITS: f32 = 10000.0;
+
+// a value the strategy plotted without naming a chart
+struct TickPlot {
+    time: i64,
+    series: String,
+    value: f32,
+}
+
 pub struct Simulation {
     id: i32,
     in_sample: bool,
@@ -15,14 +35,34 @@ pub struct Simulation {
 
     charts: Vec<Chart>,
 
+    cross_indicators: Vec<CrossIndicator>,
+
+    tick_statistics: TickStatistics,
+
     deposit: f32,
+    // the deposit plus the profit of the closed trades, added up as they close
+    closed_balance: f32,
     last_equity_high: f32,
     last_equity_low: f32,
     highest_drawdown: f32,
     drawdown_limit: f32,
 
+    // for the free margin
+    leverage: f32,
+
     open_trades: Vec<Trade>,
     closed_trades: Vec<Trade>,
+
+    // set when a trade is opened, closed or modified, see take_positions_changed()
+    positions_changed: bool,
+
+    // trades opened and closed since the strategy was last told, see take_trade_events()
+    trade_events: Vec<TradeEvent>,
+
+    // what the strategy log()s, created by the first message
+    strategy_log: Option<File>,
+
+    plots: Vec<TickPlot>,
 }
 
 impl Simulation {
@@ -32,24 +72,33 @@ impl Simulation {
         }
     }
 
-    // "balance" is the sum of deposit + closed trades
-    pub fn balance(&self) -> f32 {
-        let mut balance = self.deposit;
-
-        for trade in self.closed_trades.iter() {
-            balance += trade.profit();
+    pub fn bind_variables(&mut self, vars: &RangeBoundVariables) -> Result<(), String> {
+        for chart in self.charts.iter_mut() {
+            try!(chart.bind_variables(vars));
         }
 
+        Ok(())
+    }
+
+    // "balance" is the sum of deposit + closed trades.  Like the equity and free margin it's in
+    // pips of a size 1 trade.
+    pub fn balance(&self) -> f32 {
         match self.jpy_base {
-            true  => balance / 100.0,
-            false => balance,
+            true  => self.closed_balance / 100.0,
+            false => self.closed_balance,
         }
     }
 
-    // returns true when all the attached charts are fully populated with data
+    // returns true when every indicator on the attached charts and across them has warmed up
     pub fn can_trade(&mut self) -> bool {
-        for chart in self.charts.iter_mut() {
-            if !chart.has_full_data() {
+        for chart in self.charts.iter() {
+            if !chart.is_ready() {
+                return false;
+            }
+        }
+
+        for cross_indicator in self.cross_indicators.iter() {
+            if !cross_indicator.is_ready() {
                 return false;
             }
         }
@@ -57,9 +106,9 @@ impl Simulation {
         true
     }
 
-    pub fn close_all_open_trades(&mut self, tick: &Tick) {
+    pub fn close_all_open_trades(&mut self, tick: &Tick, reason: ExitReason) {
         for trade in self.open_trades.iter_mut() {
-            trade.close(tick);
+            trade.close(tick, reason);
         }
 
         self.migrate_closed_trades();
@@ -99,14 +148,60 @@ impl Simulation {
         }
     }
 
+    // open or closed
+    pub fn find_trade(&self, id: i32) -> Option<&Trade> {
+        match self.open_trades.iter().find( |t| t.get_id() == id ) {
+            Some(trade) => Some(trade),
+            None        => self.closed_trades.iter().find( |t| t.get_id() == id ),
+        }
+    }
+
+    // percentage the equity is below its last high, <= 0
+    pub fn current_drawdown(&self) -> f32 {
+        -(100.0 - ((self.last_equity_low / self.last_equity_high) * 100.0))
+    }
+
+    // Equity less the margin the open trades tie up, in pips like the balance and equity.  The
+    // margin is held in the quote currency, and a pip of a size 1 trade is worth
+    // TRADE_UNITS * pip size of it, so dividing by that turns the margin into pips.
+    pub fn free_margin(&self) -> f32 {
+        let pip_size = match self.jpy_base {
+            true  => 0.01,
+            false => 0.0001,
+        };
+
+        let pip_value = TRADE_UNITS * pip_size;
+
+        let mut margin = 0.0f32;
+
+        for trade in self.open_trades.iter() {
+            let held = trade.size * TRADE_UNITS * trade.open_price / self.leverage;
+            margin += held / pip_value;
+        }
+
+        self.equity() - margin
+    }
+
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
     pub fn get_highest_drawdown(&self) -> f32 {
         self.highest_drawdown
     }
 
+    pub fn get_tick_statistics(&self) -> &TickStatistics {
+        &self.tick_statistics
+    }
+
     pub fn has_exceeded_max_drawdown(&self) -> bool {
         self.highest_drawdown < self.drawdown_limit
     }
 
+    pub fn get_open_trades(&self) -> &Vec<Trade> {
+        &self.open_trades
+    }
+
     pub fn has_open_trades(&self) -> bool {
         self.open_trades.len() > 0
     }
@@ -119,6 +214,45 @@ impl Simulation {
         }
     }
 
+    pub fn log_charts(&self, logfile: &mut File) {
+        for chart in self.charts.iter() {
+            chart.log_exports(self.id, logfile);
+        }
+
+        for plot in self.plots.iter() {
+            let time = time::at_utc(time::Timespec::new(plot.time, 0));
+
+            let s = format!(
+                "{},strategy,{},{},{}\n",
+                self.id,
+                utilities::tm_to_iso(time),
+                plot.series,
+                plot.value,
+            );
+
+            logfile.write(s.as_bytes()).ok().unwrap();
+        }
+    }
+
+    // Appends to output/simulation_<id>.log.  Messages are written as they come so they're there
+    // even when the strategy fails later on.
+    pub fn log_message(&mut self, tick: &Tick, message: &LogMessage) {
+        if self.strategy_log.is_none() {
+            let path = Path::new(format!("output/simulation_{}.log", self.id));
+
+            self.strategy_log = Some(File::create(&path).ok().unwrap());
+        }
+
+        let s = format!(
+            "{} [{}] {}\n",
+            utilities::tm_to_iso(tick.time),
+            message.level,
+            message.message,
+        );
+
+        self.strategy_log.as_mut().unwrap().write(s.as_bytes()).ok().unwrap();
+    }
+
     pub fn log_ticks(&self, logfile: &mut File) {
         for trade in self.closed_trades.iter() {
             for tick in trade.ticks.iter() {
@@ -139,27 +273,72 @@ impl Simulation {
             println!("NOTE: CSV file has JPY base currency");
         }
 
+        // checked against the same charts when the config was loaded
+        let cross_indicators = config::expect_valid(
+            parsers::build_cross_indicators(&config::get().cross_indicators, &charts, true)
+        );
+
         Simulation {
             id: Simulation::next_id(),
             in_sample: in_sample,
             jpy_base: jpy_base,
             deposit: deposit,
+            closed_balance: deposit,
             last_equity_high: deposit,
             last_equity_low: deposit,
             closed_trades: vec!(),
             open_trades: vec!(),
+            positions_changed: true,
+            trade_events: vec!(),
+            strategy_log: None,
+            plots: vec!(),
             charts: charts,
+            cross_indicators: cross_indicators,
+            tick_statistics: TickStatistics::new(config::get().tick_window),
             drawdown_limit: -10.0, // TODO: make this configurable
             highest_drawdown: 0.0,
+            leverage: config::get().leverage as f32,
         }
     }
 
-    pub fn open_long_trade(&mut self, tick: &Tick) {
-        self.record_new_trade(Trade::new_long_trade(tick));
-    }
+    // Carries out one of the orders the strategy placed on `tick`.  Closing or modifying a trade
+    // that has already closed, e.g. on its stop loss, does nothing.
+    pub fn execute_order(&mut self, order: &Order, tick: &Tick) -> Result<(), LuaError> {
+        match order.action {
+            OrderAction::Buy | OrderAction::Sell => {
+                self.record_new_trade(Trade::new_from_order(order, tick));
+            },
+            OrderAction::CloseAll => self.close_all_open_trades(tick, ExitReason::Strategy),
+            OrderAction::Close | OrderAction::Modify => {
+                match self.open_trades.iter_mut().find( |t| t.get_id() == order.id ) {
+                    Some(trade) => {
+                        match order.action {
+                            OrderAction::Close => trade.close(tick, ExitReason::Strategy),
+                            _                  => {
+                                trade.modify(order);
+                                self.positions_changed = true;
+                            },
+                        }
+                    },
+                    None => {
+                        if !self.closed_trades.iter().any( |t| t.get_id() == order.id ) {
+                            let call = match order.action {
+                                OrderAction::Close => "close",
+                                _                  => "modify",
+                            };
+
+                            return Err(LuaError::new(
+                                format!("{}({}): there's no trade with that id", call, order.id)
+                            ));
+                        }
+                    },
+                }
+
+                self.migrate_closed_trades();
+            },
+        }
 
-    pub fn open_short_trade(&mut self, tick: &Tick) {
-        self.record_new_trade(Trade::new_short_trade(tick));
+        Ok(())
     }
 
     pub fn migrate_closed_trades(&mut self) {
@@ -177,10 +356,20 @@ impl Simulation {
 
         indexes.reverse();
 
+        // oldest first
+        for &index in indexes.iter().rev() {
+            self.trade_events.push(TradeEvent::Closed(self.open_trades[index].get_id()));
+        }
+
         for &index in indexes.iter() {
             let trade = self.open_trades.remove(index);
+            self.closed_balance += trade.profit();
             self.closed_trades.push(trade);
         }
+
+        if 0 != indexes.len() {
+            self.positions_changed = true;
+        }
     }
 
     pub fn next_id() -> i32 {
@@ -206,8 +395,45 @@ impl Simulation {
         self.balance() - self.deposit
     }
 
+    // onto the named chart's last completed candle, or onto `tick`
+    pub fn record_plot(&mut self, tick: &Tick, point: &PlotPoint) -> Result<(), LuaError> {
+        match point.chart {
+            Some(ref name) => {
+                match self.charts.iter_mut().find( |c| c.get_name() == name.as_slice() ) {
+                    Some(chart) => chart.record_plot(point.series.as_slice(), point.value),
+                    None        => return Err(LuaError::new(
+                        format!("plot({}): there's no chart named {}", point.series, name)
+                    )),
+                }
+            },
+            None => {
+                self.plots.push(TickPlot {
+                    time: tick.time.to_timespec().sec,
+                    series: point.series.clone(),
+                    value: point.value,
+                });
+            },
+        }
+
+        Ok(())
+    }
+
     fn record_new_trade(&mut self, trade: Trade) {
+        self.trade_events.push(TradeEvent::Opened(trade.get_id()));
         self.open_trades.push(trade);
+        self.positions_changed = true;
+    }
+
+    // closes the trades whose stop loss or take profit `tick` reached
+    pub fn process_stops(&mut self, tick: &Tick) {
+        for trade in self.open_trades.iter_mut() {
+            match trade.stop_hit(tick) {
+                Some(reason) => trade.close(tick, reason),
+                None         => {},
+            }
+        }
+
+        self.migrate_closed_trades();
     }
 
     pub fn record_tick_onto_trades(&mut self, tick: &Tick) {
@@ -216,10 +442,44 @@ impl Simulation {
         }
     }
 
-    pub fn update_charts(&mut self, tick: &Tick) {
+    // true when the open trades have changed since the last call, not just their profits
+    pub fn take_positions_changed(&mut self) -> bool {
+        mem::replace(&mut self.positions_changed, false)
+    }
+
+    // hands out the events queued since the last call, oldest first
+    pub fn take_trade_events(&mut self) -> Vec<TradeEvent> {
+        mem::replace(&mut self.trade_events, vec!())
+    }
+
+    // returns the names of the charts that completed a candle on `tick`
+    pub fn update_charts(&mut self, tick: &Tick, lua: &Lua) -> Result<Vec<String>, LuaError> {
+        let mut completed: Vec<String> = vec!();
+
         for chart in self.charts.iter_mut() {
-            chart.process_tick(tick);
+            if try!(chart.process_tick(tick, Some(lua))) {
+                completed.push(chart.get_name().to_string());
+            }
+        }
+
+        if completed.len() > 0 {
+            self.update_cross_indicators(lua);
         }
+
+        Ok(completed)
+    }
+
+    fn update_cross_indicators(&mut self, lua: &Lua) {
+        for indicator in self.cross_indicators.iter_mut() {
+            let (a, b) = indicator.get_chart_indexes();
+
+            indicator.update(&self.charts[a], &self.charts[b]);
+            indicator.send_to_lua(lua);
+        }
+    }
+
+    pub fn update_tick_statistics(&mut self, tick: &Tick) {
+        self.tick_statistics.push(tick);
     }
 
     pub fn update_drawdown(&mut self) {
@@ -232,7 +492,7 @@ impl Simulation {
             self.last_equity_low = equity;
         }
 
-        let drawdown = -(100.0 - ((self.last_equity_low / self.last_equity_high) * 100.0));
+        let drawdown = self.current_drawdown();
 
         if drawdown < self.highest_drawdown {
             self.highest_drawdown = drawdown;
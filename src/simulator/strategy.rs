@@ -1,13 +1,54 @@
-use lua;
+use config;
+use lua::{Lua, LuaError};
 use range_bound_variable::RangeBoundVariables;
 use simulation::Simulation;
 use tick::Tick;
+use trade::{Trade, TradeEvent};
+
+// see Strategy::execute_orders()
+static MAX_ORDER_ROUNDS: uint = 100;
 
 #[derive(Clone)]
 pub struct Strategy {
     path: String,
 }
 
+// leaves a table describing `trade` on top of the stack
+fn push_trade(lua: &Lua, trade: &Trade) {
+    lua.create_table(0);
+
+    lua.set_field_integer("id", trade.get_id());
+    lua.set_field_string("direction", match trade.is_long() {
+        true  => "long",
+        false => "short",
+    });
+    lua.set_field_number("size", trade.size);
+    lua.set_field_number("open_price", trade.open_price);
+    lua.set_field_integer("open_time", trade.opened_at.to_timespec().sec as i32);
+    lua.set_field_number("profit", trade.profit());
+    lua.set_field_integer("magic", trade.magic);
+    lua.set_field_string("comment", trade.comment.as_slice());
+
+    match trade.stop_loss {
+        Some(price) => lua.set_field_number("stop_loss", price),
+        None        => {},
+    }
+
+    match trade.take_profit {
+        Some(price) => lua.set_field_number("take_profit", price),
+        None        => {},
+    }
+
+    match trade.exit_reason {
+        Some(reason) => {
+            lua.set_field_number("close_price", trade.close_price);
+            lua.set_field_integer("close_time", trade.closed_at.to_timespec().sec as i32);
+            lua.set_field_string("exit_reason", reason.name());
+        },
+        None => {},
+    }
+}
+
 impl Strategy {
     pub fn new(path: &str) -> Strategy {
         Strategy {
@@ -15,31 +56,224 @@ impl Strategy {
         }
     }
 
-    pub fn on_tick(&self, sim: &mut Simulation, tick: &Tick) {
-        lua::register_number("current_bid", tick.bid);
-        lua::register_number("current_ask", tick.ask);
-        lua::register_number("current_spread", tick.ask - tick.bid);
+    // Refills the callbacks' tick and ctx arguments, the account table and what positions()
+    // returns.  The positions are only rebuilt when a trade was opened, closed or modified.
+    fn publish(&self, lua: &Lua, sim: &mut Simulation, tick: &Tick) {
+        lua.set_tick(tick, sim);
+
+        match sim.take_positions_changed() {
+            true  => lua.publish_positions(sim.get_open_trades()),
+            false => lua.update_position_profits(sim.get_open_trades()),
+        }
+    }
+
+    // Writes out what the strategy log()ged and plot()ted since the last call.  Calls made from
+    // on_start() get the first tick's time.
+    fn collect_output(&self,
+                      lua: &Lua,
+                      sim: &mut Simulation,
+                      tick: &Tick) -> Result<(), LuaError> {
+        let (messages, points) = lua.take_output();
+
+        for message in messages.iter() {
+            sim.log_message(tick, message);
+        }
+
+        for point in points.iter() {
+            try!(sim.record_plot(tick, point));
+        }
+
+        Ok(())
+    }
+
+    // Calls the strategy's `function` with its `nargs` arguments followed by tick and ctx, and
+    // writes out its output, even when it fails part way.
+    fn call(&self,
+            lua: &Lua,
+            sim: &mut Simulation,
+            tick: &Tick,
+            function: &str,
+            nargs: i32) -> Result<(), LuaError> {
+        lua.push_tick_arguments();
+
+        let result = lua.call(function, nargs + 2);
+
+        try!(self.collect_output(lua, sim, tick));
+
+        result
+    }
+
+    // Carries out the orders the last callback placed, then reports the trades they opened or
+    // closed.  The orders those callbacks place are carried out in the next round, and so on
+    // until a round opens and closes nothing.  A strategy that keeps going, e.g. by hedging
+    // every trade it opens, is stopped after MAX_ORDER_ROUNDS.
+    fn execute_orders(&self,
+                      lua: &Lua,
+                      sim: &mut Simulation,
+                      tick: &Tick) -> Result<(), LuaError> {
+        for _ in range(0, MAX_ORDER_ROUNDS) {
+            for order in lua.take_orders().iter() {
+                try!(sim.execute_order(order, tick));
+            }
+
+            let events = sim.take_trade_events();
+
+            if 0 == events.len() {
+                return Ok(());
+            }
+
+            try!(self.report_trade_events(lua, sim, tick, events));
+        }
+
+        Err(LuaError::new(format!(
+            "on_trade_opened() and on_trade_closed() were still opening or closing trades after \
+             {} rounds of orders",
+            MAX_ORDER_ROUNDS
+        )))
+    }
+
+    // Calls on_trade_opened() and on_trade_closed() for the trades opened and closed since the
+    // last call, e.g. on their stop loss before the strategy runs, and carries out the orders
+    // they place.
+    pub fn process_trade_events(&self,
+                                lua: &Lua,
+                                sim: &mut Simulation,
+                                tick: &Tick) -> Result<(), LuaError> {
+        self.execute_orders(lua, sim, tick)
+    }
+
+    fn report_trade_events(&self,
+                           lua: &Lua,
+                           sim: &mut Simulation,
+                           tick: &Tick,
+                           events: Vec<TradeEvent>) -> Result<(), LuaError> {
+        self.publish(lua, sim, tick);
+
+        for event in events.iter() {
+            let (function, id) = match *event {
+                TradeEvent::Opened(id) => ("on_trade_opened", id),
+                TradeEvent::Closed(id) => ("on_trade_closed", id),
+            };
+
+            if !lua.has_function(function) {
+                continue;
+            }
+
+            push_trade(lua, sim.find_trade(id).unwrap());
+            try!(self.call(lua, sim, tick, function, 1));
+        }
+
+        Ok(())
+    }
+
+    // Calls on_start() with a table of the simulation's variables.  There's no tick yet, so
+    // orders placed here are dropped.
+    pub fn on_start(&self, lua: &Lua, vars: &RangeBoundVariables) -> Result<(), LuaError> {
+        if !lua.has_function("on_start") {
+            return Ok(());
+        }
+
+        vars.push_table(lua);
+        try!(lua.call("on_start", 1));
+
+        lua.take_orders();
+
+        Ok(())
+    }
+
+    pub fn on_candle_close(&self,
+                           lua: &Lua,
+                           sim: &mut Simulation,
+                           tick: &Tick,
+                           chart_name: &str) -> Result<(), LuaError> {
+        if !lua.has_function("on_candle_close") {
+            return Ok(());
+        }
+
+        self.publish(lua, sim, tick);
+
+        lua.push_table_string(chart_name);
+        try!(self.call(lua, sim, tick, "on_candle_close", 1));
+
+        self.execute_orders(lua, sim, tick)
+    }
+
+    pub fn on_tick(&self,
+                   lua: &Lua,
+                   sim: &mut Simulation,
+                   tick: &Tick) -> Result<(), LuaError> {
+        if !lua.has_function("on_tick") {
+            return Ok(());
+        }
+
+        self.publish(lua, sim, tick);
 
-        lua::register_boolean("has_open_trades", sim.has_open_trades());
+        try!(self.call(lua, sim, tick, "on_tick", 0));
 
-        match lua::on_tick() {
-            lua::TradeDecision::LONG  => sim.open_long_trade(tick),
-            lua::TradeDecision::SHORT => sim.open_short_trade(tick),
-            lua::TradeDecision::CLOSE => sim.close_all_open_trades(tick),
-            lua::TradeDecision::NOOP  => {}
+        self.execute_orders(lua, sim, tick)
+    }
+
+    // Reports the trades closed at the end of the simulation, then calls on_finish() with its
+    // results.  Orders placed from here on are dropped.
+    pub fn on_finish(&self,
+                     lua: &Lua,
+                     sim: &mut Simulation,
+                     tick: &Tick,
+                     tick_count: i32) -> Result<(), LuaError> {
+        self.publish(lua, sim, tick);
+
+        for event in sim.take_trade_events().iter() {
+            match *event {
+                TradeEvent::Closed(id) if lua.has_function("on_trade_closed") => {
+                    push_trade(lua, sim.find_trade(id).unwrap());
+                    try!(self.call(lua, sim, tick, "on_trade_closed", 1));
+                },
+                _ => {},
+            }
         }
+
+        lua.take_orders();
+
+        if !lua.has_function("on_finish") {
+            return Ok(());
+        }
+
+        lua.create_table(0);
+        lua.set_field_number("profit", sim.profit());
+        lua.set_field_integer("trades", sim.closed_trades_count() as i32);
+        lua.set_field_integer("long_trades", sim.closed_long_trade_count() as i32);
+        lua.set_field_integer("short_trades", sim.closed_short_trade_count() as i32);
+        lua.set_field_number("pip_expectancy", sim.pip_expectancy());
+        lua.set_field_number("max_drawdown", sim.get_highest_drawdown());
+        lua.set_field_number("balance", sim.balance());
+        lua.set_field_integer("ticks", tick_count);
+
+        try!(self.call(lua, sim, tick, "on_finish", 1));
+
+        lua.take_orders();
+
+        Ok(())
     }
 
-    pub fn setup(&self, vars: RangeBoundVariables) {
-        lua::setup(self.path.as_slice());
+    // Starts an interpreter for one simulation.  It's stopped when the returned handle is
+    // dropped.  math.random() is seeded from SEED and the simulation's id.
+    pub fn setup(&self,
+                 vars: RangeBoundVariables,
+                 simulation_id: i32) -> Result<Lua, LuaError> {
+        let config = config::get();
 
-        vars.register_in_lua();
+        let lua = try!(Lua::new_sandboxed(
+            self.path.as_slice(),
+            &config.sandbox,
+            config.seed,
+            simulation_id,
+        ));
+
+        vars.register_in_lua(&lua);
 
         println!("Registered variables:");
         vars.print();
-    }
 
-    pub fn teardown(&self) {
-        lua::teardown();
+        Ok(lua)
     }
 }
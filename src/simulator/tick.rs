@@ -9,6 +9,10 @@ pub struct Tick {
     pub time: time::Tm,
     pub bid: f32,
     pub ask: f32,
+
+    // the prices as read, for Lua, which has doubles
+    pub exact_bid: f64,
+    pub exact_ask: f64,
 }
 
 impl Tick {
@@ -24,8 +28,14 @@ impl Tick {
         0 == utilities::day_of_week(year as uint, month as uint, day as uint)
     }
 
-    pub fn new(time: time::Tm, bid: f32, ask: f32) -> Tick {
-        Tick { time: time, bid: bid, ask: ask }
+    pub fn new(time: time::Tm, bid: f64, ask: f64) -> Tick {
+        Tick {
+            time: time,
+            bid: bid as f32,
+            ask: ask as f32,
+            exact_bid: bid,
+            exact_ask: ask,
+        }
     }
 
     pub fn new_from_line(l: String) -> Tick {
@@ -36,8 +46,8 @@ impl Tick {
 
         let parts: Vec<&str> = line.as_slice().split(',').collect();
         let tm = utilities::tm_from_string(parts[0]);
-        let bid = utilities::string_to_float(parts[1]);
-        let ask = utilities::string_to_float(parts[2]);
+        let bid = utilities::string_to_double(parts[1]);
+        let ask = utilities::string_to_double(parts[2]);
 
         Tick::new(tm, bid, ask)
     }
@@ -0,0 +1,152 @@
+// Tick-level measures over a rolling window of seconds, updated on every tick the simulation
+// processes.  Tick times only have whole seconds, so the window is too.
+
+use std::collections::RingBuf;
+use std::num::Float;
+
+use tick::Tick;
+
+#[derive(Clone,Copy)]
+struct WindowTick {
+    time: i64,
+    spread: f32,
+
+    // squared log return of the mid price since the previous tick
+    squared_return: f64,
+}
+
+#[derive(Clone)]
+pub struct TickStatistics {
+    window: i64,
+
+    ticks: RingBuf<WindowTick>,
+
+    // Ticks whose spread is larger than every later tick's, oldest first, so the front is
+    // always the window's maximum.
+    spread_maxima: RingBuf<WindowTick>,
+
+    spread_sum: f64,
+    squared_return_sum: f64,
+
+    last_time: Option<i64>,
+    last_mid: f32,
+    seconds_since_last_tick: i64,
+}
+
+impl TickStatistics {
+    pub fn new(window: i32) -> TickStatistics {
+        if window < 1 {
+            panic!("tick statistics window must be > 0 seconds, got {}", window);
+        }
+
+        TickStatistics {
+            window: window as i64,
+            ticks: RingBuf::new(),
+            spread_maxima: RingBuf::new(),
+            spread_sum: 0.0,
+            squared_return_sum: 0.0,
+            last_time: None,
+            last_mid: 0.0,
+            seconds_since_last_tick: 0,
+        }
+    }
+
+    pub fn push(&mut self, tick: &Tick) {
+        let time   = tick.time.to_timespec().sec;
+        let spread = tick.ask - tick.bid;
+        let mid    = (tick.bid + tick.ask) / 2.0;
+
+        let squared_return = match self.last_time {
+            Some(last_time) => {
+                self.seconds_since_last_tick = time - last_time;
+
+                match self.last_mid > 0.0 && mid > 0.0 {
+                    true  => ((mid as f64) / (self.last_mid as f64)).ln().powi(2),
+                    false => 0.0,
+                }
+            },
+            None => 0.0,
+        };
+
+        self.last_time = Some(time);
+        self.last_mid  = mid;
+
+        let window_tick = WindowTick {
+            time: time,
+            spread: spread,
+            squared_return: squared_return,
+        };
+
+        self.ticks.push_back(window_tick);
+        self.spread_sum += spread as f64;
+        self.squared_return_sum += squared_return;
+
+        loop {
+            match self.spread_maxima.back() {
+                Some(last) if last.spread <= spread => {},
+                _                                   => break,
+            }
+
+            self.spread_maxima.pop_back();
+        }
+
+        self.spread_maxima.push_back(window_tick);
+
+        self.expire(time);
+    }
+
+    // drops the ticks that have fallen out of the window ending at `time`
+    fn expire(&mut self, time: i64) {
+        let oldest = time - self.window;
+
+        loop {
+            let expired = match self.ticks.front() {
+                Some(first) if first.time <= oldest => *first,
+                _                                   => break,
+            };
+
+            self.ticks.pop_front();
+            self.spread_sum -= expired.spread as f64;
+            self.squared_return_sum -= expired.squared_return;
+        }
+
+        loop {
+            match self.spread_maxima.front() {
+                Some(first) if first.time <= oldest => {},
+                _                                   => break,
+            }
+
+            self.spread_maxima.pop_front();
+        }
+    }
+
+    // ticks per second over the window
+    pub fn tick_velocity(&self) -> f32 {
+        self.ticks.len() as f32 / self.window as f32
+    }
+
+    pub fn spread_average(&self) -> f32 {
+        match self.ticks.len() {
+            0 => 0.0,
+            n => (self.spread_sum / n as f64) as f32,
+        }
+    }
+
+    pub fn spread_max(&self) -> f32 {
+        match self.spread_maxima.front() {
+            Some(first) => first.spread,
+            None        => 0.0,
+        }
+    }
+
+    // square root of the summed squared log returns of the mid price over the window
+    pub fn realized_volatility(&self) -> f32 {
+        // the running sum can drift slightly below 0
+        self.squared_return_sum.max(0.0).sqrt() as f32
+    }
+
+    // between the latest tick and the one before it
+    pub fn seconds_since_last_tick(&self) -> i64 {
+        self.seconds_since_last_tick
+    }
+}
@@ -3,6 +3,8 @@ extern crate time;
 // use std::fmt;
 use std::io::File;
 
+use order::{Order, OrderAction};
+use order;
 use tick::Tick;
 use utilities;
 
@@ -12,6 +14,35 @@ pub enum TradeDirection {
     SHORT,
 }
 
+// why a trade was closed
+#[derive(Clone,Copy,Show,PartialEq)]
+pub enum ExitReason {
+    StopLoss,
+    TakeProfit,
+    Strategy,        // close() or close_all()
+    DrawdownLimit,
+    EndOfSimulation,
+}
+
+impl ExitReason {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ExitReason::StopLoss        => "stop_loss",
+            ExitReason::TakeProfit      => "take_profit",
+            ExitReason::Strategy        => "strategy",
+            ExitReason::DrawdownLimit   => "drawdown_limit",
+            ExitReason::EndOfSimulation => "end_of_simulation",
+        }
+    }
+}
+
+// queued by the simulation for the strategy's on_trade_opened() and on_trade_closed()
+#[derive(Clone,Copy)]
+pub enum TradeEvent {
+    Opened(i32),
+    Closed(i32),
+}
+
 // TODO: Shouldn't this work? Gets this error: rustc --explain E0001
 // impl fmt::Show for TradeDirection {
 //     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -22,8 +53,6 @@ pub enum TradeDirection {
 //     }
 // }
 
-static mut TRADE_ID: i32 = 1;
-
 pub struct Trade {
     id: i32,
 
@@ -36,16 +65,22 @@ pub struct Trade {
 
     pub open_price:  f32, // price filled at
     pub close_price: f32, // price filled at
+    pub exit_reason: Option<ExitReason>,
     // desired_open_price: f32,
     // desired_close_price: f32,
 
     // allow_slippage: f32, // TODO: implement this
 
-    // stopLoss   []stops.StopLoss
-    // takeProfit []stops.TakeProfit
+    // multiplies the pip profit
+    pub size: f32,
+
+    // prices the trade is closed at, see stop_hit()
+    pub stop_loss: Option<f32>,
+    pub take_profit: Option<f32>,
 
-    // stop_loss_hit: bool,
-    // take_profit_hit: bool,
+    // set by the strategy to tell its trades apart
+    pub magic: i32,
+    pub comment: String,
 
     // for calculating spreads and actual slippage
     pub open_bid: f32,
@@ -77,7 +112,7 @@ pub struct Trade {
 }
 
 impl Trade {
-    pub fn close(&mut self, tick: &Tick) {
+    pub fn close(&mut self, tick: &Tick, reason: ExitReason) {
         if self.is_closed() {
             panic!("can't close a closed trade")
         }
@@ -90,6 +125,7 @@ impl Trade {
         self.close_price = close_price;
         self.close_bid = tick.bid;
         self.close_ask = tick.ask;
+        self.exit_reason = Some(reason);
 
         self.open = false;
     }
@@ -117,15 +153,26 @@ impl Trade {
         !self.is_long()
     }
 
-    pub fn new_long_trade(tick: &Tick) -> Trade {
-        Trade::new(tick, TradeDirection::LONG)
-    }
+    // `order` is a buy or sell, whose id the strategy has already been given
+    pub fn new_from_order(order: &Order, tick: &Tick) -> Trade {
+        let direction = match order.action {
+            OrderAction::Buy  => TradeDirection::LONG,
+            OrderAction::Sell => TradeDirection::SHORT,
+            _                 => panic!("can't open a trade with a {} order", order.action),
+        };
 
-    pub fn new_short_trade(tick: &Tick) -> Trade {
-        Trade::new(tick, TradeDirection::SHORT)
+        let mut trade = Trade::new(order.id, tick, direction);
+
+        trade.size        = order.size;
+        trade.stop_loss   = order::stop_level(order.stop_loss);
+        trade.take_profit = order::stop_level(order.take_profit);
+        trade.magic       = order.magic;
+        trade.comment     = order.comment.clone();
+
+        trade
     }
 
-    fn new(tick: &Tick, direction: TradeDirection) -> Trade {
+    fn new(id: i32, tick: &Tick, direction: TradeDirection) -> Trade {
         let open_price = match direction {
             TradeDirection::LONG  => tick.ask,
             TradeDirection::SHORT => tick.bid,
@@ -136,7 +183,7 @@ impl Trade {
         let tickaroo = tick.clone();
 
         let mut t = Trade {
-            id: Trade::next_id(),
+            id: id,
             open: true,
 
             direction: direction,
@@ -146,11 +193,18 @@ impl Trade {
 
             open_price:  open_price,
             close_price: 0.0,
+            exit_reason: None,
             open_bid: tickaroo.bid,
             open_ask: tickaroo.ask,
             close_bid: 0.0,
             close_ask: 0.0,
 
+            size: 1.0,
+            stop_loss: None,
+            take_profit: None,
+            magic: 0,
+            comment: String::new(),
+
             ticks: ticks,
         };
 
@@ -159,14 +213,22 @@ impl Trade {
         t
     }
 
-    pub fn next_id() -> i32 {
-        unsafe {
-            TRADE_ID += 1;
-            TRADE_ID - 1
+    // modify(): a missing value keeps the stop, 0 removes it
+    pub fn modify(&mut self, order: &Order) {
+        if order.stop_loss.is_some() {
+            self.stop_loss = order::stop_level(order.stop_loss);
+        }
+
+        if order.take_profit.is_some() {
+            self.take_profit = order::stop_level(order.take_profit);
         }
     }
 
     pub fn profit(&self) -> f32 {
+        self.pip_profit() * self.size
+    }
+
+    fn pip_profit(&self) -> f32 {
         if self.is_open() {
             let last_tick = match self.ticks.last() {
                 Some(val) => val,
@@ -189,9 +251,38 @@ impl Trade {
         self.ticks.push(tick);
     }
 
+    // Whether `tick` reaches the stop loss or take profit.  Longs close on the bid and shorts on
+    // the ask, so that's the price that's compared.
+    pub fn stop_hit(&self, tick: &Tick) -> Option<ExitReason> {
+        let long  = self.is_long();
+        let price = match long {
+            true  => tick.bid,
+            false => tick.ask,
+        };
+
+        let stop_loss_hit = match self.stop_loss {
+            Some(stop_loss) => (long && price <= stop_loss) || (!long && price >= stop_loss),
+            None            => false,
+        };
+
+        let take_profit_hit = match self.take_profit {
+            Some(take_profit) => (long && price >= take_profit) || (!long && price <= take_profit),
+            None              => false,
+        };
+
+        if stop_loss_hit {
+            Some(ExitReason::StopLoss)
+        } else if take_profit_hit {
+            Some(ExitReason::TakeProfit)
+        } else {
+            None
+        }
+    }
+
     pub fn write_csv_header(logfile: &mut File) {
         logfile.write(b"simulation_id,trade_id,in_sample,long,opened_at,closed_at,").ok().unwrap();
-        logfile.write(b"open_price,close_price,open_spread,close_spread,profit\n").ok().unwrap();
+        logfile.write(b"open_price,close_price,open_spread,close_spread,profit,").ok().unwrap();
+        logfile.write(b"size,magic,comment,exit_reason\n").ok().unwrap();
     }
 
     pub fn to_csv(&self) -> String {
@@ -200,8 +291,11 @@ impl Trade {
             TradeDirection::SHORT => false,
         };
 
+        // quoted, with quotes doubled, since the strategy can put anything in it
+        let comment = format!("\"{}\"", self.comment.replace("\"", "\"\""));
+
         let s = format!(
-            "{},{},{},{},{},{},{},{:.1}\n",
+            "{},{},{},{},{},{},{},{:.1},{},{},{},{}\n",
             long,
             utilities::tm_to_iso(self.opened_at),
             utilities::tm_to_iso(self.closed_at),
@@ -210,6 +304,13 @@ impl Trade {
             self.open_ask  - self.open_bid,
             self.close_ask - self.close_bid,
             self.profit(),
+            self.size,
+            self.magic,
+            comment,
+            match self.exit_reason {
+                Some(reason) => reason.name(),
+                None         => "",
+            },
         );
 
         s.clone()
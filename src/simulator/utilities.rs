@@ -62,6 +62,10 @@ pub fn string_to_int(s: &str) -> i32 {
     s.parse::<i32>().unwrap()
 }
 
+pub fn string_to_double(s: &str) -> f64 {
+    s.parse::<f64>().unwrap()
+}
+
 pub fn string_to_float(s: &str) -> f32 {
     s.parse::<f32>().unwrap()
 }